@@ -0,0 +1,12 @@
+#!/bin/sh
+# two Mach-O objects with DWARF for their asm lines, archived together,
+# and a thin archive whose second member (g.o) is missing from disk
+set -e
+cd "$(dirname "$0")"
+for name in f g; do
+	llvm-mc -triple x86_64-apple-macos10.15 -filetype=obj -g "$name.s" -o "$name.o"
+done
+rm -f libfg.a libthin.a
+llvm-ar rcs --format=darwin libfg.a f.o g.o
+llvm-ar rcs --thin libthin.a f.o g.o
+rm g.o
@@ -0,0 +1,5 @@
+	.text
+	.globl	_f
+_f:
+	movl	$1, %eax
+	retq
@@ -0,0 +1,6 @@
+	.text
+	.globl	_g
+_g:
+	movl	$2, %eax
+	addl	%edi, %eax
+	retq
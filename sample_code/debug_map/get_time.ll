@@ -0,0 +1,35 @@
+; a stand-in for the get_time.o the macos_x86_64 sample was linked from (its N_OSO entry),
+; rebuild with `llc -filetype=obj get_time.ll -o get_time.o`
+target datalayout = "e-m:o-p270:32:32-p271:32:32-p272:64:64-i64:64-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-apple-macosx10.15.0"
+
+declare i32 @clock_gettime(i32, i8*)
+
+define void @get_current_time() !dbg !10 {
+  %ts = alloca [16 x i8], align 8
+  %p = getelementptr [16 x i8], [16 x i8]* %ts, i64 0, i64 0, !dbg !13
+  %r = call i32 @clock_gettime(i32 6, i8* %p), !dbg !14
+  ret void, !dbg !15
+}
+
+define i32 @main() !dbg !16 {
+  call void @get_current_time(), !dbg !17
+  ret i32 0, !dbg !18
+}
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!3, !4}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: "hand written", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "get_time.c", directory: "/home/user/SourceViewer/sample_code")
+!3 = !{i32 7, !"Dwarf Version", i32 4}
+!4 = !{i32 2, !"Debug Info Version", i32 3}
+!10 = distinct !DISubprogram(name: "get_current_time", scope: !1, file: !1, line: 15, type: !11, scopeLine: 15, spFlags: DISPFlagDefinition, unit: !0)
+!11 = !DISubroutineType(types: !12)
+!12 = !{null}
+!13 = !DILocation(line: 17, column: 9, scope: !10)
+!14 = !DILocation(line: 17, column: 9, scope: !10)
+!15 = !DILocation(line: 22, column: 1, scope: !10)
+!16 = distinct !DISubprogram(name: "main", scope: !1, file: !1, line: 26, type: !11, scopeLine: 26, spFlags: DISPFlagDefinition, unit: !0)
+!17 = !DILocation(line: 27, column: 5, scope: !16)
+!18 = !DILocation(line: 28, column: 5, scope: !16)
@@ -0,0 +1,222 @@
+#!/usr/bin/env python3
+"""Writes inline.pdb, a tiny PDB 7.0 used by the tests in src/pdb.rs.
+
+One module with one function, main (section 1, 0x0..0x30, main.c),
+that has helper (helper.h) inlined at 0x10..0x20:
+
+    0x00 main.c:10    0x10 helper.h:3    0x18 helper.h:4    0x20 main.c:13
+
+Check the result with `llvm-pdbutil dump -modules -l -il -symbols -ids inline.pdb`.
+"""
+import os
+import struct
+
+BLOCK_SIZE = 512
+GUID = bytes(range(1, 17))
+
+
+def pad4(data):
+    return data + b"\0" * (-len(data) % 4)
+
+
+def lf_pad(data):
+    """type records are padded with LF_PAD bytes counting down to the end"""
+    missing = -len(data) % 4
+    return data + bytes(0xF0 + n for n in range(missing, 0, -1))
+
+
+def cstr(s):
+    return s.encode() + b"\0"
+
+
+# /names: the string table every file name is an offset into
+strings = b"\0"
+offsets = {}
+for name in ["main.c", "helper.h"]:
+    offsets[name] = len(strings)
+    strings += cstr(name)
+names = struct.pack("<III", 0xEFFEEFFE, 1, len(strings)) + strings
+# hash buckets (one empty) and the name count
+names += struct.pack("<III", 1, 0, len(offsets))
+
+# IPI: the LF_FUNC_ID naming the inlinee, type index 0x1000
+func_id = lf_pad(b"\0\0" + struct.pack("<HII", 0x1601, 0, 0) + cstr("helper"))[2:]
+record = struct.pack("<H", len(func_id)) + func_id
+assert len(record) % 4 == 0
+
+
+def type_stream(records):
+    header = struct.pack(
+        "<IIIIIHHIIiIiIiI",
+        20040203,  # version
+        56,  # header size
+        0x1000,  # first type index
+        0x1000 + (1 if records else 0),
+        len(records),
+        0xFFFF,  # no hash stream
+        0xFFFF,
+        4,
+        0x3FFFF,
+        0, 0, 0, 0, 0, 0,
+    )
+    assert len(header) == 56
+    return header + records
+
+
+tpi = type_stream(b"")
+ipi = type_stream(record)
+
+# module stream: symbols then C13 line info
+def symbol(kind, body):
+    body = pad4(b"\0\0" + struct.pack("<H", kind) + body)[2:]
+    return struct.pack("<H", len(body)) + body
+
+
+S_GPROC32_ID = 0x1147
+S_FRAMEPROC = 0x1012
+S_INLINESITE = 0x114D
+S_INLINESITE_END = 0x114E
+S_PROC_ID_END = 0x114F
+
+proc = struct.pack("<IIIIIII", 0, 0, 0, 0x30, 0, 0x30, 0) + struct.pack("<IHB", 0, 1, 0) + cstr("main")
+# a record the reader has no use for, it has to be skipped
+frameproc = struct.pack("<IIIIIHI", 0x28, 0, 0, 0, 0, 0, 0)
+annotations = bytes(
+    [
+        3, 0x10,  # change code offset: row at 0x10, line 3
+        6, 2,  # change line offset +1
+        3, 8,  # change code offset: row at 0x18, line 4
+        4, 8,  # change code length: ends at 0x20
+    ]
+)
+inline_site = struct.pack("<III", 0, 0, 0x1000) + annotations
+symbols = (
+    symbol(S_GPROC32_ID, proc)
+    + symbol(S_FRAMEPROC, frameproc)
+    + symbol(S_INLINESITE, inline_site)
+    + symbol(S_INLINESITE_END, b"")
+    + symbol(S_PROC_ID_END, b"")
+)
+
+
+def subsection(kind, data):
+    return pad4(struct.pack("<II", kind, len(data)) + data)
+
+
+# checksum entries: name offset, checksum size, kind, padded to 4
+checksums = b""
+checksum_at = {}
+for name in ["main.c", "helper.h"]:
+    checksum_at[name] = len(checksums)
+    checksums += pad4(struct.pack("<IBB", offsets[name], 0, 0))
+
+lines = struct.pack("<IHHI", 0, 1, 0, 0x30)
+rows = [(0x00, 10), (0x10, 11), (0x20, 13)]
+lines += struct.pack("<III", checksum_at["main.c"], len(rows), 12 + 8 * len(rows))
+for offset, line in rows:
+    lines += struct.pack("<II", offset, line | 0x8000_0000)
+
+inlinee_lines = struct.pack("<I", 0) + struct.pack("<III", 0x1000, checksum_at["helper.h"], 3)
+
+c13 = (
+    subsection(0xF4, checksums)
+    + subsection(0xF2, lines)
+    + subsection(0xF6, inlinee_lines)
+)
+# ends with the (empty) list of global symbol refs
+module = struct.pack("<I", 4) + symbols + c13 + struct.pack("<I", 0)
+
+# stream numbers: 0 old directory, 1 pdb, 2 tpi, 3 dbi, 4 ipi, 5 module, 6 /names
+MODULE_STREAM = 5
+NAMES_STREAM = 6
+
+module_info = struct.pack("<I", 0)
+module_info += struct.pack("<HHiiIHHII", 1, 0, 0, 0x30, 0x60500020, 0, 0, 0, 0)
+module_info += struct.pack("<HH", 0, MODULE_STREAM)
+module_info += struct.pack("<III", 4 + len(symbols), 0, len(c13))
+module_info += struct.pack("<HHIII", 2, 0, 0, 0, 0)
+module_info += cstr("inline.obj") + cstr("inline.obj")
+module_info = pad4(module_info)
+
+# just the Ver60 header, no contributions
+section_contribs = struct.pack("<I", 0xEFFE0000 + 19970605)
+# one section: the map header then one entry
+section_map = struct.pack("<HH", 1, 1) + struct.pack("<HHHHHHII", 0x10D, 0, 0, 1, 0xFFFF, 0xFFFF, 0, 0x30)
+# file info: one module with two files
+file_info = struct.pack("<HH", 1, 2) + struct.pack("<H", 0) + struct.pack("<H", 2)
+name_buffer = cstr("main.c") + cstr("helper.h")
+file_info += struct.pack("<II", 0, len(cstr("main.c"))) + name_buffer
+file_info = pad4(file_info)
+# edit and continue names, an empty string table
+ec_names = struct.pack("<III", 0xEFFEEFFE, 1, 1) + b"\0" + struct.pack("<III", 1, 0, 0)
+# optional debug headers, all missing
+debug_headers = struct.pack("<" + "H" * 11, *([0xFFFF] * 11))
+
+dbi = struct.pack(
+    "<iIIHHHHHHIIIIIIIIHHI",
+    -1,
+    19990903,
+    1,
+    0xFFFF,  # globals
+    0,
+    0xFFFF,  # publics
+    0,
+    0xFFFF,  # symbol records
+    0,
+    len(module_info),
+    len(section_contribs),
+    len(section_map),
+    len(file_info),
+    0,
+    0,
+    len(debug_headers),
+    len(ec_names),
+    0,
+    0x8664,
+    0,
+)
+assert len(dbi) == 64
+dbi += module_info + section_contribs + section_map + file_info + ec_names + debug_headers
+
+# PDB info stream with the named stream map holding /names
+name_strings = cstr("/names")
+pdb = struct.pack("<III", 20000404, 0, 1) + GUID
+pdb += struct.pack("<I", len(name_strings)) + name_strings
+pdb += struct.pack("<II", 1, 1)  # size, capacity
+pdb += struct.pack("<II", 1, 1)  # present bit vector
+pdb += struct.pack("<I", 0)  # deleted bit vector
+pdb += struct.pack("<II", 0, NAMES_STREAM)
+pdb += struct.pack("<II", 0, 20140508)  # no more hash data, VC140 feature
+
+streams = [b"", pdb, tpi, dbi, ipi, module, names]
+
+# MSF: superblock, free block maps in blocks 1 and 2, streams, directory, block map
+blocks = [b"", b"", b""]
+
+
+def place(data):
+    first = len(blocks)
+    for i in range(0, len(data), BLOCK_SIZE):
+        blocks.append(data[i : i + BLOCK_SIZE])
+    return list(range(first, len(blocks)))
+
+
+stream_blocks = [place(data) for data in streams]
+directory = struct.pack("<I", len(streams))
+directory += b"".join(struct.pack("<I", len(data)) for data in streams)
+for numbers in stream_blocks:
+    directory += b"".join(struct.pack("<I", n) for n in numbers)
+directory_blocks = place(directory)
+block_map = place(b"".join(struct.pack("<I", n) for n in directory_blocks))[0]
+
+num_blocks = len(blocks)
+superblock = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0"
+superblock += struct.pack("<IIIIII", BLOCK_SIZE, 1, num_blocks, len(directory), 0, block_map)
+blocks[0] = superblock
+# every block in use, free block map bits are 1 for free blocks
+blocks[1] = b"\0" * BLOCK_SIZE
+blocks[2] = b"\0" * BLOCK_SIZE
+
+out = b"".join(block.ljust(BLOCK_SIZE, b"\0") for block in blocks)
+with open(os.path.join(os.path.dirname(os.path.abspath(__file__)), "inline.pdb"), "wb") as f:
+    f.write(out)
@@ -1,7 +1,8 @@
+use crate::debug_files::PathRemap;
 use clap::builder::ValueParser;
 use clap::CommandFactory;
-use clap::{Parser, Subcommand, ValueEnum};
-use colored::{control::SHOULD_COLORIZE, Colorize};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use colored::control::SHOULD_COLORIZE;
 use std::path::PathBuf;
 
 /// When colorized output should be shown
@@ -23,6 +24,58 @@ pub fn apply_color_mode(mode: ColorMode) {
     }
 }
 
+// Shared options for how debug info is located
+#[derive(Args, Debug, Clone, Default)]
+pub struct LoadOpts {
+    #[arg(
+        long = "debug-dir",
+        value_name = "DIR",
+        help = "Extra root to search for separate debug files (build-id and debuglink), \
+                on top of /usr/lib/debug and the debug config file"
+    )]
+    pub debug_dirs: Vec<PathBuf>,
+
+    #[arg(
+        long = "remap",
+        value_name = "FROM=TO",
+        value_parser = remap_parser(),
+        help = "Read sources recorded under FROM from TO instead (like -ffile-prefix-map in reverse), \
+                tried before the rules in the debug config file"
+    )]
+    pub remap: Vec<(PathBuf, PathBuf)>,
+
+    #[arg(
+        long = "pdb",
+        value_name = "PATH",
+        help = "PDB to read for PE binaries, used when its GUID matches the binary's CodeView record \
+                (or the binary has none)"
+    )]
+    pub pdbs: Vec<PathBuf>,
+
+    #[arg(
+        long = "arch",
+        value_name = "ARCH",
+        help = "Slice of a universal Mach-O binary to load (x86_64, arm64, ...), \
+                repeat it to load several, e.g. to walk both ISAs side by side"
+    )]
+    pub arches: Vec<String>,
+
+    #[arg(
+        long,
+        visible_alias = "bias",
+        value_name = "BIAS",
+        value_parser = number_parser(),
+        default_value_t = 0,
+        help = "Load bias of the running process (where a PIE or shared library was mapped), \
+                shown addresses are shifted by it and typed ones have it taken off"
+    )]
+    pub base: u64,
+}
+
+fn remap_parser() -> ValueParser {
+    ValueParser::new(PathRemap::parse_rule)
+}
+
 // Shared options for commands that take **one** binary
 #[derive(Parser, Debug, Clone)]
 pub struct SingleBinOpts {
@@ -44,6 +97,9 @@ pub struct SingleBinOpts {
         help = "Input binary/object file to process"
     )]
     pub bin: PathBuf,
+
+    #[command(flatten)]
+    pub load: LoadOpts,
 }
 
 // Shared options for commands that take **multiple** binaries
@@ -65,6 +121,9 @@ pub struct MultiBinOpts {
     #[arg(value_name = "BINS", required = true, num_args(1..),
           help = "Input binary/object files to process")]
     pub bins: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub load: LoadOpts,
 }
 
 #[derive(Debug, Clone)]
@@ -78,17 +137,32 @@ fn file_selection_parser() -> ValueParser {
         if let Ok(index) = s.parse::<usize>() {
             Ok(FileSelection::Index(index))
         } else {
-            let path = std::fs::canonicalize(PathBuf::from(s))
-                .map_err(|e| format!("Error canonicalizing path {}: {}", s, e))?;
-            if path.exists() {
-                Ok(FileSelection::Path(path))
-            } else {
-                Err(format!("'{}' is not a valid index or existing path", s))
-            }
+            //paths that do not exist here are checked once the binary is loaded, it may have the source embedded
+            let path = std::fs::canonicalize(s).unwrap_or_else(|_| PathBuf::from(s));
+            Ok(FileSelection::Path(path))
         }
     })
 }
 
+fn number_parser() -> ValueParser {
+    ValueParser::new(|s: &str| -> Result<u64, String> {
+        let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => s.parse::<u64>(),
+        };
+        parsed.map_err(|e| format!("'{}' is not a valid number: {}", s, e))
+    })
+}
+
+/// addresses are hex like addr2line takes them, the 0x is optional
+pub fn parse_address(s: &str) -> Result<u64, String> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(hex, 16).map_err(|e| format!("'{}' is not a hex address: {}", s, e))
+}
+
 #[derive(Parser, Debug, Clone)]
 pub struct ViewSource {
     #[command(flatten)]
@@ -163,6 +237,13 @@ pub enum Commands {
 
         #[arg(help = "line numer to start from")]
         line: Option<usize>,
+
+        #[arg(
+            long,
+            value_name = "CORE",
+            help = "ELF core dump of BIN, opens at the faulting line with the asm of every frame selected"
+        )]
+        core: Option<PathBuf>,
     },
 
     #[command(about = "Dumps sections information for each file")]
@@ -181,6 +262,24 @@ pub enum Commands {
             help = "If set, instructions we could not find a symbol for will be omitted"
         )]
         ignore_unknown: bool,
+
+        #[arg(
+            long,
+            help = "Print the functions each instruction was inlined into, with their call sites"
+        )]
+        inline_chain: bool,
+
+        #[arg(
+            long,
+            help = "Show where the local variables are (register or stack slot) at each instruction"
+        )]
+        vars: bool,
+
+        #[arg(
+            long,
+            help = "Show the CFA rule and saved registers (from .eh_frame/.debug_frame) at each instruction"
+        )]
+        cfa: bool,
     },
 
     #[command(about = "Dumps functions found in the file")]
@@ -206,16 +305,73 @@ pub enum Commands {
     },
 
     #[command(
-        about = format!(
-            "{}: {}",
-            "Not Finished".red(),
-            "Dumps the DWARF debug information in the files"
-        ),
+        about = "Dumps the DWARF debug information entries (DIE tree) in the files",
         visible_aliases = ["dwarf_dump"]
     )]
     DwarfDump {
         #[command(flatten)]
         opts: MultiBinOpts,
+
+        #[arg(
+            long,
+            value_name = "NAME",
+            help = "Only dump compile units whose name contains NAME"
+        )]
+        cu: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "OFFSET",
+            value_parser = number_parser(),
+            help = "Only dump the DIE starting at this .debug_info offset (hex with 0x) and its children"
+        )]
+        offset: Option<u64>,
+    },
+
+    #[command(
+        about = "Checks that every function in the code sections has an FDE in .eh_frame or .debug_frame"
+    )]
+    Cfi {
+        #[command(flatten)]
+        opts: MultiBinOpts,
+    },
+
+    #[command(
+        about = "Prints the layout of a struct, class, union or enum: member offsets, sizes, holes and padding"
+    )]
+    Types {
+        #[command(flatten)]
+        opts: SingleBinOpts,
+
+        #[arg(value_name = "NAME", help = "Type to look for, e.g. Foo or ns::Foo")]
+        name: String,
+    },
+
+    #[command(
+        about = "Turns addresses into function (with its inline chain), file, line and column"
+    )]
+    Symbolize {
+        #[command(flatten)]
+        opts: SingleBinOpts,
+
+        #[arg(
+            value_name = "ADDRS",
+            value_parser = ValueParser::new(parse_address),
+            help = "Addresses in hex (0x is optional), read from stdin when none are given"
+        )]
+        addresses: Vec<u64>,
+
+        #[arg(long, help = "Print one JSON object per address")]
+        json: bool,
+    },
+
+    #[command(
+        about = "Appends function and file:line to the addresses in a log read from stdin",
+        visible_aliases = ["annotate_log"]
+    )]
+    AnnotateLog {
+        #[command(flatten)]
+        opts: MultiBinOpts,
     },
 
     #[command(
@@ -241,14 +397,17 @@ pub enum Commands {
 impl Commands {
     pub fn get_color(&self) -> ColorMode {
         match self {
-            Commands::Walk { opts, .. } | Commands::ViewSource(ViewSource { opts, .. }) => {
-                opts.color
-            }
+            Commands::Walk { opts, .. }
+            | Commands::ViewSource(ViewSource { opts, .. })
+            | Commands::Types { opts, .. }
+            | Commands::Symbolize { opts, .. } => opts.color,
             Commands::Sections { opts }
             | Commands::Lines { opts, .. }
             | Commands::ViewSources { opts }
             | Commands::Functions { opts }
-            | Commands::DwarfDump { opts } => opts.color,
+            | Commands::Cfi { opts }
+            | Commands::DwarfDump { opts, .. }
+            | Commands::AnnotateLog { opts, .. } => opts.color,
             Commands::ConfigPaths { color } => *color,
         }
     }
@@ -0,0 +1,212 @@
+use crate::dwarf_dump::register_name;
+use crate::file_parser::EStr;
+use crate::relocatable::SectionLayout;
+use gimli::{
+    BaseAddresses, CfaRule, CieOrFde, DebugFrame, EhFrame, EndianSlice, FrameDescriptionEntry,
+    Range, Register, RegisterRule, RunTimeEndian, UnwindContext, UnwindSection, UnwindTableRow,
+};
+use object::Object;
+use std::error::Error;
+use std::rc::Rc;
+
+/// The `.eh_frame` and `.debug_frame` of a file, ready to look up unwind rows in
+#[derive(Clone)]
+pub struct FrameTable<'a> {
+    eh_frame: Option<(EhFrame<EStr<'a>>, FdeIndex<'a>)>,
+    /// only searched for addresses `.eh_frame` does not cover
+    debug_frame: Option<(DebugFrame<EStr<'a>>, FdeIndex<'a>)>,
+    bases: BaseAddresses,
+    arch: object::Architecture,
+}
+
+impl<'a> FrameTable<'a> {
+    /// `eh_frame` and `debug_frame` are the section contents, already decompressed and relocated
+    pub fn new(
+        obj: &object::File<'a>,
+        layout: &SectionLayout,
+        eh_frame: Option<&'a [u8]>,
+        debug_frame: Option<&'a [u8]>,
+    ) -> Option<Self> {
+        let endian = if obj.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let mut bases = BaseAddresses::default();
+        if let Some(text) = obj.section_by_name(".text") {
+            bases = bases.set_text(layout.section_address(&text));
+        }
+        if let Some(got) = obj.section_by_name(".got") {
+            bases = bases.set_got(layout.section_address(&got));
+        }
+        if let Some(section) = obj.section_by_name(".eh_frame") {
+            bases = bases.set_eh_frame(layout.section_address(&section));
+        }
+
+        let eh_frame = eh_frame.map(|data| {
+            let section = EhFrame::from(EndianSlice::new(data, endian));
+            let index = FdeIndex::new(&section, &bases);
+            (section, index)
+        });
+        let debug_frame = debug_frame.map(|data| {
+            let mut section = DebugFrame::from(EndianSlice::new(data, endian));
+            section.set_address_size(if obj.is_64() { 8 } else { 4 });
+            let index = FdeIndex::new(&section, &bases);
+            (section, index)
+        });
+        if eh_frame.is_none() && debug_frame.is_none() {
+            return None;
+        }
+
+        Some(FrameTable {
+            eh_frame,
+            debug_frame,
+            bases,
+            arch: obj.architecture(),
+        })
+    }
+
+    /// The row covering the link time `address`
+    pub fn row(&self, address: u64) -> Option<UnwindTableRow<usize>> {
+        self.eh_frame
+            .as_ref()
+            .and_then(|(section, index)| index.row(section, &self.bases, address))
+            .or_else(|| {
+                let (section, index) = self.debug_frame.as_ref()?;
+                index.row(section, &self.bases, address)
+            })
+    }
+
+    /// Runs `f` on the row covering the link time `address`
+    pub fn with_row<T>(
+        &self,
+        address: u64,
+        f: impl FnOnce(&UnwindTableRow<usize>) -> T,
+    ) -> Option<T> {
+        Some(f(&self.row(address)?))
+    }
+
+    /// The CFA and saved register rules at `address`, shown as `CFA=rsp+0x10 rbp=[CFA-0x10] ra=[CFA-0x8]`
+    pub fn describe(&self, address: u64) -> Option<String> {
+        let row = self.row(address)?;
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                offset_from(&register_name(self.arch, *register), *offset)
+            }
+            CfaRule::Expression(_) => "expr".to_string(),
+        };
+        let mut parts = vec![format!("CFA={}", cfa)];
+        for (register, rule) in row.registers() {
+            let rule = match rule {
+                RegisterRule::Undefined => "undef".to_string(),
+                RegisterRule::SameValue => "same".to_string(),
+                RegisterRule::Offset(offset) => format!("[{}]", offset_from("CFA", *offset)),
+                RegisterRule::ValOffset(offset) => offset_from("CFA", *offset),
+                RegisterRule::Register(other) => register_name(self.arch, *other).into_owned(),
+                RegisterRule::Constant(value) => format!("{:#x}", value),
+                _ => "expr".to_string(),
+            };
+            parts.push(format!("{}={}", self.register(*register), rule));
+        }
+        Some(parts.join(" "))
+    }
+
+    /// Address ranges that have an FDE, sorted by start
+    pub fn fde_ranges(&self) -> Result<Vec<Range>, Box<dyn Error>> {
+        let mut ranges = Vec::new();
+        for index in [
+            &self.eh_frame.as_ref().map(|e| &e.1),
+            &self.debug_frame.as_ref().map(|d| &d.1),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if let Some(e) = index.error {
+                return Err(e.into());
+            }
+            ranges.extend(index.fdes.iter().map(|fde| Range {
+                begin: fde.initial_address(),
+                end: fde.end_address(),
+            }));
+        }
+        ranges.sort_by_key(|r| (r.begin, r.end));
+        Ok(ranges)
+    }
+
+    fn register(&self, register: Register) -> String {
+        //the return address column has no real register behind it on x86
+        if register == self.return_address_register() {
+            return "ra".to_string();
+        }
+        register_name(self.arch, register).into_owned()
+    }
+
+    fn return_address_register(&self) -> Register {
+        match self.arch {
+            object::Architecture::X86_64 => Register(16),
+            object::Architecture::I386 => Register(8),
+            _ => Register(u16::MAX),
+        }
+    }
+}
+
+fn offset_from(base: &str, offset: i64) -> String {
+    match offset {
+        0 => base.to_string(),
+        o if o < 0 => format!("{}-{:#x}", base, o.unsigned_abs()),
+        o => format!("{}+{:#x}", base, o),
+    }
+}
+
+/// The FDEs of one section sorted by start, parsed once so a lookup is a binary search
+#[derive(Clone)]
+struct FdeIndex<'a> {
+    fdes: Rc<[FrameDescriptionEntry<EStr<'a>>]>,
+    /// where reading the section stopped, the FDEs before it are still used
+    error: Option<gimli::Error>,
+}
+
+impl<'a> FdeIndex<'a> {
+    fn new<S: UnwindSection<EStr<'a>>>(section: &S, bases: &BaseAddresses) -> Self {
+        let mut fdes = Vec::new();
+        let mut entries = section.entries(bases);
+        let error = loop {
+            let partial = match entries.next() {
+                Ok(Some(CieOrFde::Fde(partial))) => partial,
+                Ok(Some(CieOrFde::Cie(_))) => continue,
+                Ok(None) => break None,
+                Err(e) => break Some(e),
+            };
+            match partial.parse(S::cie_from_offset) {
+                Ok(fde) => fdes.push(fde),
+                Err(e) => break Some(e),
+            }
+        };
+        fdes.sort_by_key(|fde| fde.initial_address());
+        FdeIndex {
+            fdes: fdes.into(),
+            error,
+        }
+    }
+
+    /// like `.eh_frame_hdr`, only the last FDE starting at or before `address` is checked
+    fn row<S: UnwindSection<EStr<'a>>>(
+        &self,
+        section: &S,
+        bases: &BaseAddresses,
+        address: u64,
+    ) -> Option<UnwindTableRow<usize>> {
+        let idx = self
+            .fdes
+            .partition_point(|fde| fde.initial_address() <= address)
+            .checked_sub(1)?;
+        let fde = &self.fdes[idx];
+        if !fde.contains(address) {
+            return None;
+        }
+        let mut ctx = UnwindContext::new();
+        fde.unwind_info_for_address(section, bases, &mut ctx, address)
+            .ok()
+            .cloned()
+    }
+}
@@ -1,4 +1,6 @@
+use crate::debug_files::PathRemap;
 use directories::ProjectDirs;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::{error::Error, fs, path::PathBuf, time::Duration};
 use tui::layout::Constraint;
@@ -12,6 +14,65 @@ pub fn get_walk_config_path() -> Option<PathBuf> {
     Some(proj.preference_dir().join("walk-config.toml"))
 }
 
+pub fn get_debug_config_path() -> Option<PathBuf> {
+    let proj = get_project_dir()?;
+    Some(proj.preference_dir().join("debug-config.toml"))
+}
+
+fn read_config<T: DeserializeOwned + Default>(path: Option<PathBuf>) -> Result<T, Box<dyn Error>> {
+    let Some(path) = path else {
+        // No home dir / config dir available → act like no config
+        return Ok(T::default());
+    };
+
+    // Case 1: file does not exist → defaults (all None)
+    let bytes = match fs::read(&path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(T::default());
+        }
+        Err(e) => {
+            // Case 3: real IO error
+            return Err(Box::new(e));
+        }
+    };
+
+    // Case 2 / 3: parse
+    let cfg: T = toml::from_slice(&bytes)?;
+    Ok(cfg)
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct DebugConfig {
+    /// extra roots searched for separate debug files, on top of /usr/lib/debug
+    #[serde(alias = "debug_dirs")]
+    pub debug_search_dirs: Option<Vec<PathBuf>>,
+
+    /// "FROM=TO" rules for sources that were built in another place
+    #[serde(alias = "remap")]
+    pub source_remap: Option<Vec<String>>,
+}
+
+impl DebugConfig {
+    pub fn get_debug_search_dirs(&self) -> &[PathBuf] {
+        self.debug_search_dirs.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_source_remap(&self) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+        self.source_remap
+            .iter()
+            .flatten()
+            .map(|rule| {
+                PathRemap::parse_rule(rule).map_err(|e| format!("debug config: {}", e).into())
+            })
+            .collect()
+    }
+
+    pub fn get_global() -> Result<Self, Box<dyn Error>> {
+        read_config(get_debug_config_path())
+    }
+}
+
 #[derive(Debug, Deserialize, Default)]
 pub struct WalkConfig {
     pub asm_percent: Option<u32>,
@@ -54,25 +115,6 @@ impl WalkConfig {
     }
 
     pub fn get_global() -> Result<Self, Box<dyn Error>> {
-        let Some(path) = get_walk_config_path() else {
-            // No home dir / config dir available → act like no config
-            return Ok(Self::default());
-        };
-
-        // Case 1: file does not exist → defaults (all None)
-        let bytes = match fs::read(&path) {
-            Ok(b) => b,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                return Ok(Self::default());
-            }
-            Err(e) => {
-                // Case 3: real IO error
-                return Err(Box::new(e));
-            }
-        };
-
-        // Case 2 / 3: parse
-        let cfg: WalkConfig = toml::from_slice(&bytes)?;
-        Ok(cfg)
+        read_config(get_walk_config_path())
     }
 }
@@ -0,0 +1,559 @@
+use crate::program_context::FileRegistry;
+use gimli::{CfaRule, RegisterRule, UnwindTableRow};
+use object::elf;
+use object::read::elf::{ElfFile64, FileHeader, ProgramHeader};
+use object::{Endian, Endianness, Object, ObjectSegment};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// registers by DWARF number, the pc is kept apart since x86 has no DWARF register for it
+pub type Registers = HashMap<u16, u64>;
+
+/// how deep a backtrace goes before we assume the stack is garbage
+const MAX_FRAMES: usize = 256;
+
+/// A file the crashed process had mapped (from the NT_FILE note)
+#[derive(Debug, Clone)]
+pub struct CoreMapping {
+    pub start: u64,
+    pub end: u64,
+    /// offset in the file the mapping starts at
+    pub offset: u64,
+    pub path: PathBuf,
+}
+
+/// The crashed thread and the memory of an ELF core file
+pub struct CoreDump<'a> {
+    pub arch: object::Architecture,
+    /// where the crashing thread stopped
+    pub pc: u64,
+    pub registers: Registers,
+    pub mappings: Vec<CoreMapping>,
+    /// the page size the NT_FILE offsets are counted in
+    page_size: u64,
+    memory: Vec<(u64, &'a [u8])>,
+    endian: Endianness,
+}
+
+impl<'a> CoreDump<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        if object::FileKind::parse(data)? == object::FileKind::Elf32 {
+            return Err("32-bit core files are not supported".into());
+        }
+        let core = ElfFile64::<Endianness>::parse(data)?;
+        let endian = core.endian();
+        if core.elf_header().e_type(endian) != elf::ET_CORE {
+            return Err("not a core file".into());
+        }
+        let arch = core.architecture();
+
+        let mut thread = None;
+        let mut mappings = Vec::new();
+        let mut page_size = 0x1000;
+        let mut memory = Vec::new();
+        for segment in core.elf_program_headers() {
+            match segment.p_type(endian) {
+                elf::PT_LOAD => {
+                    let bytes = segment
+                        .data(endian, data)
+                        .map_err(|_| "core segment is out of bounds")?;
+                    //read only file mappings are usually left out of the dump
+                    if !bytes.is_empty() {
+                        memory.push((segment.p_vaddr(endian), bytes));
+                    }
+                }
+                elf::PT_NOTE => {
+                    let Some(mut notes) = segment.notes(endian, data)? else {
+                        continue;
+                    };
+                    while let Some(note) = notes.next()? {
+                        if note.name() != b"CORE" {
+                            continue;
+                        }
+                        match note.n_type(endian) {
+                            //the kernel writes the thread that got the signal first
+                            elf::NT_PRSTATUS if thread.is_none() => {
+                                thread = Some(prstatus_registers(arch, note.desc(), endian)?);
+                            }
+                            elf::NT_FILE => {
+                                (page_size, mappings) = file_mappings(note.desc(), endian)?;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (pc, registers) = thread.ok_or("core file has no NT_PRSTATUS note")?;
+        Ok(CoreDump {
+            arch,
+            pc,
+            registers,
+            mappings,
+            page_size,
+            memory,
+            endian,
+        })
+    }
+
+    pub fn read_u64(&self, address: u64) -> Option<u64> {
+        let (start, bytes) = self
+            .memory
+            .iter()
+            .find(|(start, bytes)| *start <= address && address - start < bytes.len() as u64)?;
+        let offset = (address - start) as usize;
+        let bytes = bytes.get(offset..offset + 8)?;
+        Some(self.endian.read_u64_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// the mapping holding `address`
+    pub fn mapping_at(&self, address: u64) -> Option<&CoreMapping> {
+        self.mappings
+            .iter()
+            .find(|m| m.start <= address && address < m.end)
+    }
+
+    /// What was added to the link time addresses of `path` when it was mapped
+    pub fn load_bias(&self, path: &Path, obj: &object::File) -> Option<u64> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let start = self
+            .mappings
+            .iter()
+            .filter(|m| m.offset == 0 && same_file(&m.path, &path))
+            .map(|m| m.start)
+            .min()?;
+        //the first PT_LOAD maps the headers, mmap rounds it down to a page
+        let first = obj
+            .segments()
+            .find(|segment| segment.file_range().0 == 0)?
+            .address();
+        Some(start.wrapping_sub(first & !(self.page_size - 1)))
+    }
+
+    /// Runtime addresses of the stack, the pc first and then every return address.
+    ///
+    /// Each frame is unwound with the `.eh_frame` (or `.debug_frame`) of the file mapped there (loaded through `registry`),
+    /// where that is missing the frame pointer chain is followed instead.
+    pub fn backtrace(&self, registry: &mut FileRegistry) -> Vec<u64> {
+        let mut frames = vec![self.pc];
+        let mut registers = self.registers.clone();
+        while frames.len() < MAX_FRAMES {
+            let pc = *frames.last().unwrap();
+            //a return address is past the call, the row we want is the call's
+            let leaf = frames.len() == 1;
+            let probe = if leaf { pc } else { pc - 1 };
+            let unwound = match self.unwind_cfi(registry, probe, leaf, &registers) {
+                Some(unwound) => unwound,
+                None => self.unwind_frame_pointer(&registers),
+            };
+            let Some((ra, next)) = unwound else {
+                break;
+            };
+
+            let sp = stack_pointer(self.arch);
+            let moved_up = next.get(&sp) > registers.get(&sp);
+            if ra == 0 || !moved_up {
+                break;
+            }
+            frames.push(ra);
+            registers = next;
+        }
+        frames
+    }
+
+    /// None when there is no CFI for `probe`, Some(None) when the CFI says the stack ends there
+    fn unwind_cfi(
+        &self,
+        registry: &mut FileRegistry,
+        probe: u64,
+        leaf: bool,
+        registers: &Registers,
+    ) -> Option<Option<(u64, Registers)>> {
+        let path = self.mapping_at(probe)?.path.clone();
+        let machine = registry.get_machine(path.as_path().into()).ok()?;
+        let address = probe.wrapping_sub(self.load_bias(&path, &machine.obj)?);
+
+        machine
+            .get_frames()?
+            .with_row(address, |row| self.apply_row(row, leaf, registers))?
+    }
+
+    /// the caller's registers from the unwind row of the current frame
+    fn apply_row(
+        &self,
+        row: &UnwindTableRow<usize>,
+        leaf: bool,
+        registers: &Registers,
+    ) -> Option<Option<(u64, Registers)>> {
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                registers.get(&register.0)?.wrapping_add_signed(*offset)
+            }
+            CfaRule::Expression(_) => return None,
+        };
+
+        //registers without a rule keep their value, the callee saved ones are what matters here.
+        //gimli drops undefined rules though, so a missing return address rule ends the stack
+        //(only an arm leaf function still has it in the link register)
+        let mut next = registers.clone();
+        let ra_register = return_address_register(self.arch);
+        if !(leaf && self.arch == object::Architecture::Aarch64) {
+            next.remove(&ra_register);
+        }
+        for (register, rule) in row.registers() {
+            let value = match rule {
+                RegisterRule::Undefined => {
+                    next.remove(&register.0);
+                    continue;
+                }
+                RegisterRule::SameValue => continue,
+                RegisterRule::Offset(offset) => self.read_u64(cfa.wrapping_add_signed(*offset))?,
+                RegisterRule::ValOffset(offset) => cfa.wrapping_add_signed(*offset),
+                RegisterRule::Register(other) => *registers.get(&other.0)?,
+                RegisterRule::Constant(value) => *value,
+                _ => return None,
+            };
+            next.insert(register.0, value);
+        }
+
+        let Some(ra) = next.get(&ra_register).copied() else {
+            return Some(None);
+        };
+        next.insert(stack_pointer(self.arch), cfa);
+        Some(Some((ra, next)))
+    }
+
+    /// the classic `push rbp; mov rbp, rsp` chain (x29 on aarch64)
+    fn unwind_frame_pointer(&self, registers: &Registers) -> Option<(u64, Registers)> {
+        let fp_register = match self.arch {
+            object::Architecture::X86_64 => 6,
+            object::Architecture::Aarch64 => 29,
+            _ => return None,
+        };
+        let fp = *registers.get(&fp_register)?;
+        let caller_fp = self.read_u64(fp)?;
+        let ra = self.read_u64(fp.checked_add(8)?)?;
+        if caller_fp <= fp {
+            return None;
+        }
+
+        let mut next = registers.clone();
+        next.insert(fp_register, caller_fp);
+        next.insert(stack_pointer(self.arch), fp + 16);
+        Some((ra, next))
+    }
+}
+
+fn same_file(mapped: &Path, path: &Path) -> bool {
+    mapped == path || fs::canonicalize(mapped).is_ok_and(|mapped| mapped == path)
+}
+
+fn stack_pointer(arch: object::Architecture) -> u16 {
+    match arch {
+        object::Architecture::Aarch64 => 31,
+        _ => 7,
+    }
+}
+
+fn return_address_register(arch: object::Architecture) -> u16 {
+    match arch {
+        object::Architecture::Aarch64 => 30,
+        _ => 16,
+    }
+}
+
+/// the pc and DWARF numbered registers out of an elf_prstatus
+fn prstatus_registers(
+    arch: object::Architecture,
+    desc: &[u8],
+    endian: Endianness,
+) -> Result<(u64, Registers), Box<dyn Error>> {
+    //pr_reg comes after the signal info, pids and times, the same on every 64 bit target
+    const PR_REG: usize = 112;
+    let reg = |index: usize| -> Result<u64, Box<dyn Error>> {
+        let start = PR_REG + index * 8;
+        let bytes = desc
+            .get(start..start + 8)
+            .ok_or("NT_PRSTATUS note is too short")?;
+        Ok(endian.read_u64_bytes(bytes.try_into().unwrap()))
+    };
+
+    let mut registers = Registers::new();
+    match arch {
+        object::Architecture::X86_64 => {
+            //user_regs_struct order for rax, rdx, rcx, rbx, rsi, rdi, rbp, rsp, r8-r15
+            const ORDER: [usize; 16] = [10, 12, 11, 5, 13, 14, 4, 19, 9, 8, 7, 6, 3, 2, 1, 0];
+            for (dwarf, index) in ORDER.iter().enumerate() {
+                registers.insert(dwarf as u16, reg(*index)?);
+            }
+            Ok((reg(16)?, registers))
+        }
+        object::Architecture::Aarch64 => {
+            //x0-x30 then sp, the DWARF numbers are the same
+            for index in 0..=31 {
+                registers.insert(index as u16, reg(index)?);
+            }
+            Ok((reg(32)?, registers))
+        }
+        arch => Err(format!("reading registers of {:?} cores is not supported", arch).into()),
+    }
+}
+
+/// the page size and the mappings out of an NT_FILE note,
+/// laid out as count, page size, (start, end, page offset) per mapping and then their paths
+fn file_mappings(
+    desc: &[u8],
+    endian: Endianness,
+) -> Result<(u64, Vec<CoreMapping>), Box<dyn Error>> {
+    let word = |index: usize| -> Result<u64, Box<dyn Error>> {
+        let bytes = desc
+            .get(index * 8..index * 8 + 8)
+            .ok_or("NT_FILE note is too short")?;
+        Ok(endian.read_u64_bytes(bytes.try_into().unwrap()))
+    };
+
+    let count = word(0)? as usize;
+    let page_size = word(1)?;
+    if !page_size.is_power_of_two() {
+        return Err(format!("NT_FILE note has a bad page size {:#x}", page_size).into());
+    }
+    let names = count
+        .checked_mul(3 * 8)
+        .and_then(|table| desc.get(2 * 8 + table..))
+        .ok_or("NT_FILE note is too short")?;
+    let mut names = names.split(|b| *b == 0);
+
+    let mappings = (0..count)
+        .map(|i| {
+            let name = names.next().ok_or("NT_FILE note is missing a path")?;
+            Ok(CoreMapping {
+                start: word(2 + i * 3)?,
+                end: word(3 + i * 3)?,
+                offset: word(4 + i * 3)? * page_size,
+                path: crate::debug_files::bytes_to_path(name),
+            })
+        })
+        .collect::<Result<_, Box<dyn Error>>>()?;
+    Ok((page_size, mappings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use typed_arena::Arena;
+
+    /// where the tests pretend hello-world was mapped
+    const BIAS: u64 = 0x5555_5555_4000;
+    const STACK: u64 = 0x7ffc_0000_0000;
+
+    /// an elf_prstatus with `pr_reg[i] = 0x1000 + i` and junk everywhere else
+    fn prstatus(set: &[(usize, u64)]) -> Vec<u8> {
+        let mut desc = Vec::new();
+        //pr_info, pr_cursig and its padding
+        desc.extend([0xaau8; 12 + 2 + 2]);
+        //pr_sigpend, pr_sighold
+        desc.extend([0xbbu8; 8 + 8]);
+        //pr_pid, pr_ppid, pr_pgrp, pr_sid
+        desc.extend([0xccu8; 4 * 4]);
+        //pr_utime, pr_stime, pr_cutime, pr_cstime
+        desc.extend([0xddu8; 4 * 16]);
+        let mut regs: Vec<u64> = (0..27).map(|i| 0x1000 + i).collect();
+        for (index, value) in set {
+            regs[*index] = *value;
+        }
+        for reg in regs {
+            desc.extend(reg.to_le_bytes());
+        }
+        //pr_fpvalid and its padding
+        desc.extend([0xeeu8; 8]);
+        assert_eq!(desc.len(), 336);
+        desc
+    }
+
+    fn nt_file(page_size: u64, mappings: &[(u64, u64, u64, &str)]) -> Vec<u8> {
+        let mut desc = Vec::new();
+        desc.extend((mappings.len() as u64).to_le_bytes());
+        desc.extend(page_size.to_le_bytes());
+        for (start, end, page, _) in mappings {
+            for word in [start, end, page] {
+                desc.extend(word.to_le_bytes());
+            }
+        }
+        for (.., path) in mappings {
+            desc.extend(path.as_bytes());
+            desc.push(0);
+        }
+        desc
+    }
+
+    /// an x86_64 ELF core with one note segment and a PT_LOAD per memory range
+    fn core_file(notes: &[(u32, Vec<u8>)], memory: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut note_data = Vec::new();
+        for (n_type, desc) in notes {
+            note_data.extend(5u32.to_le_bytes());
+            note_data.extend((desc.len() as u32).to_le_bytes());
+            note_data.extend(n_type.to_le_bytes());
+            note_data.extend(b"CORE\0\0\0\0");
+            note_data.extend(desc);
+            note_data.resize(note_data.len().next_multiple_of(4), 0);
+        }
+
+        let phnum = 1 + memory.len();
+        let mut offset = 64 + 56 * phnum as u64;
+        let mut headers = Vec::new();
+        let mut phdr = |p_type: u32, vaddr: u64, size: u64, align: u64| {
+            headers.extend(p_type.to_le_bytes());
+            headers.extend(4u32.to_le_bytes());
+            for word in [offset, vaddr, 0, size, size, align] {
+                headers.extend(word.to_le_bytes());
+            }
+            offset += size;
+        };
+        phdr(elf::PT_NOTE, 0, note_data.len() as u64, 4);
+        for (address, bytes) in memory {
+            phdr(elf::PT_LOAD, *address, bytes.len() as u64, 0x1000);
+        }
+
+        let mut data = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+        data.resize(16, 0);
+        data.extend(elf::ET_CORE.to_le_bytes());
+        data.extend(elf::EM_X86_64.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        //e_entry, e_phoff, e_shoff
+        for word in [0u64, 64, 0] {
+            data.extend(word.to_le_bytes());
+        }
+        data.extend(0u32.to_le_bytes());
+        for half in [64u16, 56, phnum as u16, 64, 0, 0] {
+            data.extend(half.to_le_bytes());
+        }
+        assert_eq!(data.len(), 64);
+
+        data.extend(headers);
+        data.extend(note_data);
+        for (_, bytes) in memory {
+            data.extend(bytes);
+        }
+        data
+    }
+
+    fn stack(words: &[u64]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    fn hello_world() -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_code/hello-world");
+        fs::canonicalize(path)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn reads_x86_64_registers() {
+        let data = core_file(&[(elf::NT_PRSTATUS, prstatus(&[]))], &[]);
+        let core = CoreDump::parse(&data).unwrap();
+        assert_eq!(core.arch, object::Architecture::X86_64);
+        //rip
+        assert_eq!(core.pc, 0x1000 + 16);
+        let expected = [
+            (0, 10), //rax
+            (1, 12), //rdx
+            (2, 11), //rcx
+            (3, 5),  //rbx
+            (4, 13), //rsi
+            (5, 14), //rdi
+            (6, 4),  //rbp
+            (7, 19), //rsp
+            (8, 9),  //r8
+            (9, 8),
+            (10, 7),
+            (11, 6),
+            (12, 3),
+            (13, 2),
+            (14, 1),
+            (15, 0), //r15
+        ];
+        for (dwarf, index) in expected {
+            assert_eq!(core.registers[&dwarf], 0x1000 + index, "register {}", dwarf);
+        }
+        assert_eq!(core.registers.len(), 16);
+    }
+
+    #[test]
+    fn reads_file_mappings() {
+        let path = hello_world();
+        let files = nt_file(
+            0x1000,
+            &[
+                (BIAS, BIAS + 0x1000, 0, &path),
+                (BIAS + 0x1000, BIAS + 0x2000, 1, &path),
+                (0x7f00_0000_0000, 0x7f00_0002_0000, 0x28, "/lib/libc.so.6"),
+            ],
+        );
+        let (page_size, mappings) = file_mappings(&files, Endianness::Little).unwrap();
+        assert_eq!(page_size, 0x1000);
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(mappings[1].start, BIAS + 0x1000);
+        assert_eq!(mappings[1].offset, 0x1000);
+        assert_eq!(mappings[2].offset, 0x28000);
+        assert_eq!(mappings[2].path, Path::new("/lib/libc.so.6"));
+
+        assert!(file_mappings(&files[..files.len() - 20], Endianness::Little).is_err());
+        assert!(file_mappings(&nt_file(0x1001, &[]), Endianness::Little).is_err());
+    }
+
+    #[test]
+    fn backtrace_follows_frame_pointers() {
+        //nothing is mapped at the pc, so there is no CFI to go by
+        let regs = [(16, 0x40_1000), (4, STACK + 0x10), (19, STACK)];
+        let memory = stack(&[
+            0,
+            0,
+            STACK + 0x30, //rbp of the caller
+            0x40_2000,    //its return address
+            0,
+            0,
+            STACK + 0x50,
+            0x40_3000,
+            0,
+            0,
+            0, //the outermost frame has no rbp
+            0,
+        ]);
+        let data = core_file(&[(elf::NT_PRSTATUS, prstatus(&regs))], &[(STACK, memory)]);
+        let core = CoreDump::parse(&data).unwrap();
+        let arena = Arena::new();
+        let mut registry = FileRegistry::new(&arena);
+        assert_eq!(
+            core.backtrace(&mut registry),
+            vec![0x40_1000, 0x40_2000, 0x40_3000]
+        );
+    }
+
+    #[test]
+    fn backtrace_uses_cfi() {
+        //hello-world's main right before `push rbp`, the return address is at rsp.
+        //rbp is junk so the frame pointer chain would go nowhere
+        let pc = BIAS + 0x114d;
+        let regs = [(16, pc), (4, 0), (19, STACK)];
+        let path = hello_world();
+        let files = nt_file(0x1000, &[(BIAS, BIAS + 0x5000, 0, &path)]);
+        let data = core_file(
+            &[(elf::NT_PRSTATUS, prstatus(&regs)), (elf::NT_FILE, files)],
+            &[(STACK, stack(&[0x7f00_0000_1234, 0]))],
+        );
+        let core = CoreDump::parse(&data).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let obj = object::File::parse(&*bytes).unwrap();
+        assert_eq!(core.load_bias(Path::new(&path), &obj), Some(BIAS));
+
+        let arena = Arena::new();
+        let mut registry = FileRegistry::new(&arena);
+        assert_eq!(core.backtrace(&mut registry), vec![pc, 0x7f00_0000_1234]);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::file_parser::fat_slices;
+use object::Object;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Roots that are always searched for separate debug files
+pub const DEFAULT_DEBUG_DIRS: &[&str] = &["/usr/lib/debug"];
+
+/// A debug companion found on disk, not yet parsed
+pub struct FoundDebugFile {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+}
+
+#[cfg(unix)]
+pub fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Looks for the file holding the DWARF of a stripped binary.
+///
+/// Mach-O files check for a `.dSYM` bundle with the same LC_UUID next to them.
+/// Otherwise this follows the same order as gdb: the build-id tree under each debug root first,
+/// then the `.gnu_debuglink` name next to the binary, in its `.debug` dir
+/// and mirrored under each debug root.
+pub fn find_separate_debug_file(
+    obj: &object::File,
+    path: &Path,
+    debug_dirs: &[PathBuf],
+) -> Option<FoundDebugFile> {
+    if let Ok(Some(uuid)) = obj.mach_uuid() {
+        if let Some(found) = find_dsym(path, uuid) {
+            return Some(found);
+        }
+    }
+
+    if let Ok(Some(build_id)) = obj.build_id() {
+        if let Some(found) = find_by_build_id(build_id, debug_dirs) {
+            return Some(found);
+        }
+    }
+
+    if let Ok(Some((name, crc))) = obj.gnu_debuglink() {
+        if let Some(found) = find_by_debuglink(path, &bytes_to_path(name), crc, debug_dirs) {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// Finds the dwz supplementary file named by `.gnu_debugaltlink`.
+///
+/// `path` is the file holding the link, a relative name is resolved against its directory.
+/// The build-id in the link has to match so a stale file is never used.
+pub fn find_supplementary_file(
+    obj: &object::File,
+    path: &Path,
+    debug_dirs: &[PathBuf],
+) -> Option<FoundDebugFile> {
+    let (name, build_id) = obj.gnu_debugaltlink().ok()??;
+    let name = bytes_to_path(name);
+
+    let mut candidates = Vec::new();
+    if let Some(dir) = fs::canonicalize(path)
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_owned))
+    {
+        candidates.push(dir.join(&name));
+    }
+    //the recorded path is absolute on the build machine, a debug root may hold a copy
+    for root in debug_dirs {
+        let relative = name.strip_prefix("/usr/lib/debug").unwrap_or(&name);
+        let relative = relative.strip_prefix("/").unwrap_or(relative);
+        candidates.push(root.join(relative));
+    }
+
+    candidates
+        .into_iter()
+        .find_map(|candidate| {
+            let data = fs::read(&candidate).ok()?;
+            let obj = object::File::parse(&*data).ok()?;
+            if obj.build_id().ok()?? != build_id {
+                return None;
+            }
+            drop(obj);
+
+            Some(FoundDebugFile {
+                path: candidate,
+                data,
+            })
+        })
+        .or_else(|| find_by_build_id(build_id, debug_dirs))
+}
+
+/// Finds the PDB of a PE file through its CodeView debug directory entry.
+///
+/// `pdbs` (from the command line) are tried first, then the recorded path, the same name next to
+/// the binary and under each debug root (flat or in the symbol server `name/GUIDage/name` layout).
+/// The GUID has to match, a PE without a CodeView entry only takes a PDB given in `pdbs`.
+pub fn find_pdb_file(
+    obj: &object::File,
+    path: &Path,
+    pdbs: &[PathBuf],
+    debug_dirs: &[PathBuf],
+) -> Option<FoundDebugFile> {
+    let Ok(Some(code_view)) = obj.pdb_info() else {
+        return pdbs.iter().find_map(|candidate| {
+            Some(FoundDebugFile {
+                path: candidate.clone(),
+                data: fs::read(candidate).ok()?,
+            })
+        });
+    };
+
+    //the path is from the build machine, usually a windows one
+    let recorded = String::from_utf8_lossy(code_view.path()).into_owned();
+    let name = recorded.rsplit(['\\', '/']).next().unwrap_or(&recorded);
+
+    let mut candidates = pdbs.to_vec();
+    if Path::new(&recorded).is_absolute() {
+        candidates.push(PathBuf::from(&recorded));
+    }
+    if let Some(dir) = fs::canonicalize(path)
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_owned))
+    {
+        candidates.push(dir.join(name));
+    }
+    let signature = format!("{}{:X}", guid_string(code_view.guid()), code_view.age());
+    for root in debug_dirs {
+        candidates.push(root.join(name));
+        candidates.push(root.join(name).join(&signature).join(name));
+    }
+
+    candidates.into_iter().find_map(|candidate| {
+        let data = fs::read(&candidate).ok()?;
+        if crate::pdb::pdb_guid(&data).ok()? != code_view.guid() {
+            return None;
+        }
+        Some(FoundDebugFile {
+            path: candidate,
+            data,
+        })
+    })
+}
+
+/// the raw GUID bytes as symbol servers print them (the first three fields are little endian)
+fn guid_string(guid: [u8; 16]) -> String {
+    let data1 = u32::from_le_bytes(guid[0..4].try_into().unwrap());
+    let data2 = u16::from_le_bytes(guid[4..6].try_into().unwrap());
+    let data3 = u16::from_le_bytes(guid[6..8].try_into().unwrap());
+    let rest: String = guid[8..].iter().map(|b| format!("{:02X}", b)).collect();
+    format!("{:08X}{:04X}{:04X}{}", data1, data2, data3, rest)
+}
+
+/// `Foo.dSYM` next to `Foo` first, then any other bundle in the same directory
+fn find_dsym(path: &Path, uuid: [u8; 16]) -> Option<FoundDebugFile> {
+    let binary = fs::canonicalize(path).ok()?;
+    let dir = binary.parent()?;
+
+    let mut bundle = binary.clone().into_os_string();
+    bundle.push(".dSYM");
+    let bundle = PathBuf::from(bundle);
+    let mut bundles = vec![bundle.clone()];
+
+    //dsymutil -o lets the bundle be called anything
+    if let Ok(entries) = fs::read_dir(dir) {
+        bundles.extend(
+            entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "dSYM") && *p != bundle),
+        );
+    }
+
+    bundles.iter().find_map(|bundle| {
+        let dwarf_dir = bundle.join("Contents").join("Resources").join("DWARF");
+        fs::read_dir(dwarf_dir).ok()?.find_map(|entry| {
+            let path = entry.ok()?.path();
+            let data = fs::read(&path).ok()?;
+
+            //a bundle made from a universal binary holds one slice per arch
+            let data = match fat_slices(&data).ok()? {
+                Some(slices) => slices
+                    .into_iter()
+                    .find(|(_, slice)| has_uuid(slice, uuid))?
+                    .1
+                    .to_vec(),
+                None if has_uuid(&data, uuid) => data,
+                None => return None,
+            };
+
+            Some(FoundDebugFile { path, data })
+        })
+    })
+}
+
+fn has_uuid(data: &[u8], uuid: [u8; 16]) -> bool {
+    object::File::parse(data).is_ok_and(|obj| obj.mach_uuid().ok().flatten() == Some(uuid))
+}
+
+fn build_id_path(root: &Path, build_id: &[u8]) -> Option<PathBuf> {
+    let (first, rest) = build_id.split_first()?;
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut name: String = rest.iter().map(|b| format!("{:02x}", b)).collect();
+    name.push_str(".debug");
+    Some(
+        root.join(".build-id")
+            .join(format!("{:02x}", first))
+            .join(name),
+    )
+}
+
+fn find_by_build_id(build_id: &[u8], debug_dirs: &[PathBuf]) -> Option<FoundDebugFile> {
+    debug_dirs.iter().find_map(|root| {
+        let path = build_id_path(root, build_id)?;
+        let data = fs::read(&path).ok()?;
+
+        // a stale link in the build-id tree is not our file
+        let obj = object::File::parse(&*data).ok()?;
+        if obj.build_id().ok()?? != build_id {
+            return None;
+        }
+        drop(obj);
+
+        Some(FoundDebugFile { path, data })
+    })
+}
+
+fn find_by_debuglink(
+    path: &Path,
+    link: &Path,
+    crc: u32,
+    debug_dirs: &[PathBuf],
+) -> Option<FoundDebugFile> {
+    let binary = fs::canonicalize(path).ok()?;
+    let dir = binary.parent()?;
+
+    let mut candidates = vec![dir.join(link), dir.join(".debug").join(link)];
+    for root in debug_dirs {
+        // join would drop the root for an absolute dir
+        let relative = dir.strip_prefix("/").unwrap_or(dir);
+        candidates.push(root.join(relative).join(link));
+    }
+
+    candidates.into_iter().find_map(|candidate| {
+        if candidate == binary {
+            return None;
+        }
+        let data = fs::read(&candidate).ok()?;
+        if crc32fast::hash(&data) != crc {
+            return None;
+        }
+        Some(FoundDebugFile {
+            path: candidate,
+            data,
+        })
+    })
+}
+
+/// Prefix rewrites from the paths recorded at build time to where the sources live here.
+///
+/// This is `-ffile-prefix-map` in reverse, the first rule whose FROM is a prefix wins.
+#[derive(Debug, Clone, Default)]
+pub struct PathRemap {
+    pub rules: Vec<(PathBuf, PathBuf)>,
+}
+
+impl PathRemap {
+    /// Makes every TO absolute (canonical when it exists) so remapped paths
+    /// are the same ones walk gets from the directory it lists
+    pub fn new(rules: Vec<(PathBuf, PathBuf)>) -> Self {
+        let rules = rules
+            .into_iter()
+            .map(|(from, to)| {
+                let to = fs::canonicalize(&to)
+                    .or_else(|_| std::path::absolute(&to))
+                    .unwrap_or(to);
+                (from, to)
+            })
+            .collect();
+        PathRemap { rules }
+    }
+
+    /// parses a `FROM=TO` rule
+    pub fn parse_rule(s: &str) -> Result<(PathBuf, PathBuf), String> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() => Ok((from.into(), to.into())),
+            _ => Err(format!("'{}' is not a FROM=TO remap rule", s)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn apply<'p>(&self, path: &'p Path) -> Cow<'p, Path> {
+        for (from, to) in &self.rules {
+            //strip_prefix works on whole components so /src does not match /srcs
+            if let Ok(rest) = path.strip_prefix(from) {
+                return if rest.as_os_str().is_empty() {
+                    Cow::Owned(to.clone())
+                } else {
+                    Cow::Owned(to.join(rest))
+                };
+            }
+        }
+        Cow::Borrowed(path)
+    }
+
+    /// the TO of the rule that produced `path`, if a rule did
+    pub fn remapped_root(&self, path: &Path) -> Option<&Path> {
+        self.rules
+            .iter()
+            .map(|(_, to)| to.as_path())
+            .find(|to| path.starts_with(to))
+    }
+}
@@ -0,0 +1,182 @@
+use crate::debug_files::{bytes_to_path, PathRemap};
+use crate::errors::WrapedError;
+use crate::file_parser::{EStr, LocationRow, MachineFile};
+use crate::program_context::archive_member_path;
+use addr2line::Context;
+use fallible_iterator::FallibleIterator;
+use object::{Object, ObjectMap, ObjectMapFile, ObjectSymbol};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// An object file from a debug map, loaded with its own DWARF
+pub struct ObjectDebug<'a> {
+    pub path: Arc<Path>,
+    ctx: Arc<Context<EStr<'a>>>,
+    symbols: HashMap<&'a [u8], u64>,
+}
+
+impl<'a> ObjectDebug<'a> {
+    pub fn new(path: Arc<Path>, machine: &MachineFile<'a>) -> Result<Self, Box<dyn Error>> {
+        let symbols = machine
+            .obj
+            .symbols()
+            .filter(|s| s.is_definition())
+            .filter_map(|s| Some((s.name_bytes().ok()?, machine.layout.symbol_address(&s))))
+            .collect();
+
+        Ok(ObjectDebug {
+            path,
+            ctx: machine.get_addr2line()?,
+            symbols,
+        })
+    }
+}
+
+/// The STABS debug map of a Mach-O binary that was linked without dsymutil.
+///
+/// The binary only records which function came from which `.o`,
+/// the DWARF stays in the objects at their own (unlinked) addresses.
+pub struct DebugMap<'a> {
+    map: ObjectMap<'a>,
+    pub objects: Vec<Result<ObjectDebug<'a>, WrapedError>>,
+}
+
+impl<'a> DebugMap<'a> {
+    pub fn new(map: ObjectMap<'a>, objects: Vec<Result<ObjectDebug<'a>, WrapedError>>) -> Self {
+        DebugMap { map, objects }
+    }
+
+    /// the context holding `address` and the address to ask it for
+    pub fn locate(&self, address: u64) -> Option<(&Context<EStr<'a>>, u64)> {
+        let entry = self.map.get(address)?;
+        let object = self.objects.get(entry.object_index())?.as_ref().ok()?;
+        let base = object.symbols.get(entry.name())?;
+        Some((&object.ctx, base + (address - entry.address())))
+    }
+
+    /// line rows in `low..high` given in binary addresses, sorted by address
+    pub fn find_location_range(
+        &self,
+        low: u64,
+        high: u64,
+    ) -> Result<Vec<LocationRow<'_>>, Box<dyn Error>> {
+        let mut ans = Vec::new();
+        for entry in self.map.symbols() {
+            let start = entry.address();
+            let end = start + entry.size();
+            if entry.size() == 0 || end <= low || start >= high {
+                continue;
+            }
+            let Some((ctx, base)) = self.locate(start) else {
+                continue;
+            };
+
+            let mut iter = ctx.find_location_range(base, base + entry.size())?;
+            while let Some((obj_low, size, loc)) = FallibleIterator::next(&mut iter)? {
+                //rows can run past the function in the object, clip them to what got linked
+                let row_low = obj_low.max(base);
+                let row_end = (obj_low + size).min(base + entry.size());
+                if row_end <= row_low {
+                    continue;
+                }
+                ans.push((start + (row_low - base), row_end - row_low, loc));
+            }
+        }
+        Ok(ans)
+    }
+}
+
+/// Where a debug map object lives on disk, archive members are named `lib.a(member.o)`
+///
+/// `remap` is applied to the recorded path first, objects are usually left behind in a build dir
+pub fn object_path(file: &ObjectMapFile, remap: &PathRemap) -> PathBuf {
+    let recorded = bytes_to_path(file.path());
+    let path = remap.apply(&recorded).into_owned();
+    match file.member() {
+        None => path,
+        Some(member) => archive_member_path(&path, &String::from_utf8_lossy(member)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaps_sample_objects() {
+        let sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_code/build");
+        let data = std::fs::read(sample.join("macos_x86_64")).unwrap();
+        let obj = object::File::parse(&*data).unwrap();
+        let remap = PathRemap {
+            rules: vec![("/home/user/.cache/zig/o".into(), sample.clone())],
+        };
+
+        let map = obj.object_map();
+        let paths: Vec<PathBuf> = map
+            .objects()
+            .iter()
+            .map(|file| object_path(file, &remap))
+            .collect();
+        assert!(!paths.is_empty());
+        for path in &paths {
+            assert!(
+                path.starts_with(&sample),
+                "{} was not remapped",
+                path.display()
+            );
+        }
+        //archive members keep their member name after the remapped archive path
+        assert!(paths
+            .iter()
+            .any(|p| p.to_string_lossy().ends_with(".a(libcompiler_rt.a.o)")));
+    }
+
+    #[test]
+    fn loads_remapped_sample_object() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_code");
+        let arena = typed_arena::Arena::new();
+        let mut registry = crate::program_context::FileRegistry::new(&arena);
+        //where zig left get_time.o, sample_code/debug_map holds a stand-in for it
+        let cache = "/home/user/.cache/zig/o/608be00ed672ee2761cbba1b67a09851";
+        registry.source_remap = PathRemap {
+            rules: vec![(cache.into(), root.join("debug_map"))],
+        }
+        .into();
+
+        let machine = registry
+            .get_machine(root.join("build/macos_x86_64").into())
+            .unwrap();
+        let debug_map = machine.debug_map.clone().unwrap();
+        assert!(debug_map
+            .objects
+            .iter()
+            .flatten()
+            .any(|o| o.path.ends_with("debug_map/get_time.o")));
+
+        //_main in the linked binary
+        let locator = machine.get_locator().unwrap();
+        let loc = locator.find_location(0x1_0003_1a80).unwrap().unwrap();
+        assert!(loc.file.unwrap().ends_with("get_time.c"));
+        assert_eq!(loc.line, Some(26));
+    }
+
+    #[test]
+    fn archive_members_use_placed_symbols() {
+        //sample_code/archive/build.sh makes it, g.o comes after f.o
+        let lib = Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_code/archive/libfg.a");
+        let arena = typed_arena::Arena::new();
+        let mut registry = crate::program_context::FileRegistry::new(&arena);
+        let path: Arc<Path> = archive_member_path(&lib, "g.o").into();
+        let machine = registry.get_machine(path.clone()).unwrap();
+        let object = ObjectDebug::new(path, machine).unwrap();
+
+        //the member was moved past f.o and so were its symbols and DWARF
+        let g = object.symbols[&b"_g"[..]];
+        assert_ne!(g, 0);
+        let loc = object.ctx.find_location(g + 5).unwrap().unwrap();
+        assert!(loc.file.unwrap().ends_with("g.s"));
+        assert_eq!(loc.line, Some(5));
+    }
+}
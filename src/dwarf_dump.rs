@@ -0,0 +1,469 @@
+use crate::file_parser::EStr;
+use crate::println;
+use colored::*;
+use gimli::{
+    constants, AttributeValue, DebuggingInformationEntry, Dwarf, EntriesTreeNode, Expression,
+    Operation, Reader, ReaderOffset, Register, Unit, UnitOffset, UnitSectionOffset, UnitType,
+};
+use std::borrow::Cow;
+use std::error::Error;
+
+/// Which parts of the debug info a dump should cover
+#[derive(Debug, Clone, Default)]
+pub struct DumpFilter {
+    /// only units whose DW_AT_name contains this
+    pub cu_name: Option<String>,
+    /// only the DIE at this .debug_info offset (and its children)
+    pub offset: Option<u64>,
+}
+
+/// Prints the DIE tree of every matching compile unit, then the `.debug_types` units
+pub fn dump_dwarf(
+    dwarf: &Dwarf<EStr<'_>>,
+    arch: object::Architecture,
+    filter: &DumpFilter,
+) -> Result<(), Box<dyn Error>> {
+    let mut found = false;
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        let UnitSectionOffset::DebugInfoOffset(unit_start) = header.offset() else {
+            continue;
+        };
+        let unit_end = unit_start.0 + header.length_including_self();
+
+        let start = match filter.offset {
+            Some(target) => {
+                let target = target as usize;
+                if target < unit_start.0 || target >= unit_end {
+                    continue;
+                }
+                gimli::DebugInfoOffset(target).to_unit_offset(&header)
+            }
+            None => None,
+        };
+
+        let unit = dwarf.unit(header)?;
+        //an offset inside a DIE (or the unit header) would dump garbage
+        let on_die = match start {
+            Some(start) => starts_die(&unit, start)?,
+            None => filter.offset.is_none(),
+        };
+        if let (Some(target), false) = (filter.offset, on_die) {
+            return Err(format!(
+                "{:#x} is not the start of a DIE (the unit at {:#x} holds it)",
+                target, unit_start.0
+            )
+            .into());
+        }
+
+        let name = unit
+            .name
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "<unnamed>".to_string());
+
+        if let Some(wanted) = &filter.cu_name {
+            if !name.contains(wanted.as_str()) {
+                continue;
+            }
+        }
+
+        found = true;
+        println!(
+            "{} {} version {} addr_size {} (next unit at {})",
+            format!("{:#010x}:", unit_start.0).yellow(),
+            unit_kind(&unit).green().bold(),
+            unit.header.version(),
+            unit.header.address_size(),
+            format!("{:#010x}", unit_end).yellow(),
+        );
+        println!("  {} {:?}", "name".cyan(), name);
+
+        let dumper = DieDumper {
+            dwarf,
+            unit: &unit,
+            arch,
+        };
+        let mut tree = unit.entries_tree(start)?;
+        dumper.dump_node(tree.root()?, 0)?;
+        println!();
+    }
+
+    //DWARF 4 type units live in their own section, offsets and names do not point into it
+    if filter.offset.is_none() && filter.cu_name.is_none() {
+        let mut headers = dwarf.type_units();
+        while let Some(header) = headers.next()? {
+            let UnitSectionOffset::DebugTypesOffset(unit_start) = header.offset() else {
+                continue;
+            };
+            let unit_end = unit_start.0 + header.length_including_self();
+            let unit = dwarf.unit(header)?;
+
+            found = true;
+            println!(
+                "{} {} version {} addr_size {} (next unit at {})",
+                format!(".debug_types+{:#010x}:", unit_start.0).yellow(),
+                unit_kind(&unit).green().bold(),
+                unit.header.version(),
+                unit.header.address_size(),
+                format!("{:#010x}", unit_end).yellow(),
+            );
+
+            let dumper = DieDumper {
+                dwarf,
+                unit: &unit,
+                arch,
+            };
+            let mut tree = unit.entries_tree(None)?;
+            dumper.dump_node(tree.root()?, 0)?;
+            println!();
+        }
+    }
+
+    if !found {
+        match (&filter.cu_name, filter.offset) {
+            (_, Some(offset)) => {
+                println!("{}", format!("no DIE found at offset {:#x}", offset).red())
+            }
+            (Some(name), None) => {
+                println!("{}", format!("no compile unit matches {:?}", name).red())
+            }
+            (None, None) => println!("{}", "no DWARF compile units found".red()),
+        }
+    }
+
+    Ok(())
+}
+
+/// true if a DIE of `unit` starts exactly at `offset`
+fn starts_die(unit: &Unit<EStr<'_>>, offset: UnitOffset) -> Result<bool, Box<dyn Error>> {
+    let mut cursor = unit.entries();
+    while let Some((_, entry)) = cursor.next_dfs()? {
+        if entry.offset() >= offset {
+            return Ok(entry.offset() == offset);
+        }
+    }
+    Ok(false)
+}
+
+fn unit_kind(unit: &Unit<EStr<'_>>) -> String {
+    match unit.header.type_() {
+        UnitType::Compilation => "Compile Unit".to_string(),
+        UnitType::Partial => "Partial Unit".to_string(),
+        UnitType::Skeleton(_) => "Skeleton Unit".to_string(),
+        UnitType::SplitCompilation(_) => "Split Compile Unit".to_string(),
+        UnitType::Type { type_signature, .. } | UnitType::SplitType { type_signature, .. } => {
+            format!("Type Unit (signature {:#018x})", type_signature.0)
+        }
+    }
+}
+
+struct DieDumper<'d, 'data> {
+    dwarf: &'d Dwarf<EStr<'data>>,
+    unit: &'d Unit<EStr<'data>>,
+    arch: object::Architecture,
+}
+
+impl<'data> DieDumper<'_, 'data> {
+    fn dump_node(
+        &self,
+        node: EntriesTreeNode<'_, '_, '_, EStr<'data>>,
+        depth: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.dump_entry(node.entry(), depth)?;
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            self.dump_node(child, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn dump_entry(
+        &self,
+        entry: &DebuggingInformationEntry<'_, '_, EStr<'data>>,
+        depth: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let indent = "  ".repeat(depth);
+        println!(
+            "{} {}{}",
+            format!("{:#010x}:", self.global_offset(entry.offset())).yellow(),
+            indent,
+            entry.tag().to_string().blue().bold()
+        );
+
+        let mut attrs = entry.attrs();
+        while let Some(attr) = attrs.next()? {
+            println!(
+                "             {}  {:<24} {}",
+                indent,
+                attr.name().to_string().cyan(),
+                self.format_value(attr.name(), attr.value())
+            );
+        }
+
+        if entry.attr_value(constants::DW_AT_low_pc)?.is_some()
+            || entry.attr_value(constants::DW_AT_ranges)?.is_some()
+        {
+            let mut ranges = self.dwarf.die_ranges(self.unit, entry)?;
+            while let Some(range) = ranges.next()? {
+                println!(
+                    "             {}  {:<24} [{:#010x}, {:#010x})",
+                    indent,
+                    "range".magenta(),
+                    range.begin,
+                    range.end
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn global_offset(&self, offset: UnitOffset) -> usize {
+        offset
+            .to_debug_info_offset(&self.unit.header)
+            .map(|x| x.0)
+            .or_else(|| offset.to_debug_types_offset(&self.unit.header).map(|x| x.0))
+            .unwrap_or(offset.0)
+    }
+
+    fn string(&self, value: AttributeValue<EStr<'data>>) -> Option<String> {
+        self.dwarf
+            .attr_string(self.unit, value)
+            .ok()
+            .map(|s| s.to_string_lossy().into_owned())
+    }
+
+    fn format_value(&self, name: constants::DwAt, value: AttributeValue<EStr<'data>>) -> String {
+        match value {
+            AttributeValue::Addr(addr) => format!("{:#010x}", addr),
+            AttributeValue::DebugAddrIndex(index) => match self.dwarf.address(self.unit, index) {
+                Ok(addr) => format!("{:#010x} (indexed {:#x})", addr, index.0),
+                Err(_) => format!("<bad address index {:#x}>", index.0),
+            },
+            AttributeValue::Udata(x) => match name {
+                constants::DW_AT_decl_line
+                | constants::DW_AT_decl_column
+                | constants::DW_AT_call_line
+                | constants::DW_AT_call_column => x.to_string(),
+                _ => format!("{:#x}", x),
+            },
+            AttributeValue::Data1(x) => format!("{:#x}", x),
+            AttributeValue::Data2(x) => format!("{:#x}", x),
+            AttributeValue::Data4(x) => format!("{:#x}", x),
+            AttributeValue::Data8(x) => format!("{:#x}", x),
+            AttributeValue::Sdata(x) => x.to_string(),
+            AttributeValue::Flag(x) => x.to_string(),
+
+            AttributeValue::String(_)
+            | AttributeValue::DebugStrRef(_)
+            | AttributeValue::DebugStrRefSup(_)
+            | AttributeValue::DebugStrOffsetsIndex(_)
+            | AttributeValue::DebugLineStrRef(_) => match self.string(value) {
+                Some(s) => format!("{:?}", s).green().to_string(),
+                None => format!("<bad string {:?}>", value),
+            },
+
+            AttributeValue::UnitRef(offset) => {
+                let target = format!("{:#010x}", self.global_offset(offset));
+                match self.ref_name(offset) {
+                    Some(n) => format!("{} {:?}", target.yellow(), n),
+                    None => target.yellow().to_string(),
+                }
+            }
+            AttributeValue::DebugInfoRef(offset) => {
+                format!("{:#010x}", offset.0).yellow().to_string()
+            }
+            AttributeValue::DebugInfoRefSup(offset) => {
+                format!("{} (supplementary)", format!("{:#010x}", offset.0).yellow())
+            }
+            AttributeValue::DebugTypesRef(sig) => format!("signature {:#018x}", sig.0),
+
+            AttributeValue::FileIndex(index) => match self.file_name(index) {
+                Some(file) => format!("{:?}", file).green().to_string(),
+                None => format!("<file {}>", index),
+            },
+
+            AttributeValue::Exprloc(expr) => self.format_expression(expr),
+            AttributeValue::Block(data) => format_bytes(data.slice()),
+
+            AttributeValue::LocationListsRef(_) | AttributeValue::DebugLocListsIndex(_) => {
+                self.format_locations(value)
+            }
+            AttributeValue::RangeListsRef(offset) => format!("rangelist {:#x}", offset.0),
+            AttributeValue::DebugRngListsIndex(index) => {
+                format!("rangelist index {:#x}", index.0)
+            }
+
+            AttributeValue::SecOffset(x) => format!("{:#x}", x),
+            AttributeValue::DebugAddrBase(x) => format!("{:#x}", x.0),
+            AttributeValue::DebugStrOffsetsBase(x) => format!("{:#x}", x.0),
+            AttributeValue::DebugLocListsBase(x) => format!("{:#x}", x.0),
+            AttributeValue::DebugRngListsBase(x) => format!("{:#x}", x.0),
+            AttributeValue::DebugLineRef(x) => format!("line table {:#x}", x.0),
+            AttributeValue::DebugMacinfoRef(x) => format!("macinfo {:#x}", x.0),
+            AttributeValue::DebugMacroRef(x) => format!("macro {:#x}", x.0),
+            AttributeValue::DwoId(id) => format!("{:#018x}", id.0),
+
+            AttributeValue::Encoding(x) => x.to_string(),
+            AttributeValue::DecimalSign(x) => x.to_string(),
+            AttributeValue::Endianity(x) => x.to_string(),
+            AttributeValue::Accessibility(x) => x.to_string(),
+            AttributeValue::Visibility(x) => x.to_string(),
+            AttributeValue::Virtuality(x) => x.to_string(),
+            AttributeValue::Language(x) => x.to_string(),
+            AttributeValue::AddressClass(x) => x.to_string(),
+            AttributeValue::IdentifierCase(x) => x.to_string(),
+            AttributeValue::CallingConvention(x) => x.to_string(),
+            AttributeValue::Inline(x) => x.to_string(),
+            AttributeValue::Ordering(x) => x.to_string(),
+        }
+    }
+
+    /// name of a DIE in the same unit (for type references and the like)
+    fn ref_name(&self, offset: UnitOffset) -> Option<String> {
+        let entry = self.unit.entry(offset).ok()?;
+        let name = entry.attr_value(constants::DW_AT_name).ok()??;
+        self.string(name)
+    }
+
+    fn file_name(&self, index: u64) -> Option<String> {
+        let header = self.unit.line_program.as_ref()?.header();
+        let file = header.file(index)?;
+        let name = self.string(file.path_name())?;
+        match file.directory(header).and_then(|d| self.string(d)) {
+            Some(dir) if !dir.is_empty() => Some(format!("{}/{}", dir, name)),
+            _ => Some(name),
+        }
+    }
+
+    fn format_locations(&self, value: AttributeValue<EStr<'data>>) -> String {
+        let mut iter = match self.dwarf.attr_locations(self.unit, value) {
+            Ok(Some(iter)) => iter,
+            _ => return format!("<bad location list {:?}>", value),
+        };
+
+        let mut out = String::from("location list");
+        loop {
+            match iter.next() {
+                Ok(Some(entry)) => {
+                    out.push_str(&format!(
+                        "\n{:>30}[{:#010x}, {:#010x}): {}",
+                        "",
+                        entry.range.begin,
+                        entry.range.end,
+                        self.format_expression(entry.data)
+                    ));
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    out.push_str(&format!(" <error: {}>", e));
+                    break;
+                }
+            }
+        }
+        out
+    }
+
+    fn format_expression(&self, expr: Expression<EStr<'data>>) -> String {
+        format_expression(expr, self.unit.encoding(), self.arch)
+    }
+}
+
+fn format_bytes(data: &[u8]) -> String {
+    let bytes: Vec<String> = data.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("<{}>", bytes.join(" "))
+}
+
+/// Register name as the DWARF numbering for `arch` calls it
+pub fn register_name(arch: object::Architecture, register: Register) -> Cow<'static, str> {
+    use object::Architecture as A;
+    let name = match arch {
+        A::X86_64 => gimli::X86_64::register_name(register),
+        A::I386 => gimli::X86::register_name(register),
+        A::Arm => gimli::Arm::register_name(register),
+        A::Aarch64 => gimli::AArch64::register_name(register),
+        A::Riscv32 | A::Riscv64 => gimli::RiscV::register_name(register),
+        A::Mips | A::Mips64 => gimli::MIPS::register_name(register),
+        A::PowerPc64 => gimli::PowerPc64::register_name(register),
+        A::LoongArch64 => gimli::LoongArch::register_name(register),
+        _ => None,
+    };
+    match name {
+        Some(n) => n.into(),
+        None => format!("reg{}", register.0).into(),
+    }
+}
+
+/// Renders a DWARF expression as a list of DW_OP operations
+pub fn format_expression<R: Reader>(
+    expr: Expression<R>,
+    encoding: gimli::Encoding,
+    arch: object::Architecture,
+) -> String {
+    let mut ops = expr.operations(encoding);
+    let mut parts = Vec::new();
+    loop {
+        let op = match ops.next() {
+            Ok(Some(op)) => op,
+            Ok(None) => break,
+            Err(e) => {
+                parts.push(format!("<error: {}>", e));
+                break;
+            }
+        };
+
+        parts.push(match op {
+            Operation::Register { register } => {
+                format!("DW_OP_reg {}", register_name(arch, register))
+            }
+            Operation::RegisterOffset {
+                register, offset, ..
+            } => format!("DW_OP_breg {}{:+}", register_name(arch, register), offset),
+            Operation::FrameOffset { offset } => format!("DW_OP_fbreg {:+}", offset),
+            Operation::Address { address } => format!("DW_OP_addr {:#x}", address),
+            Operation::AddressIndex { index } => format!("DW_OP_addrx {:#x}", index.0.into_u64()),
+            Operation::UnsignedConstant { value } => format!("DW_OP_constu {}", value),
+            Operation::SignedConstant { value } => format!("DW_OP_consts {}", value),
+            Operation::PlusConstant { value } => format!("DW_OP_plus_uconst {}", value),
+            Operation::Piece {
+                size_in_bits,
+                bit_offset: None,
+            } => format!("DW_OP_piece {}", size_in_bits / 8),
+            Operation::Piece {
+                size_in_bits,
+                bit_offset: Some(bit_offset),
+            } => format!("DW_OP_bit_piece {} {}", size_in_bits, bit_offset),
+            Operation::Deref { size, space, .. } => match space {
+                true => format!("DW_OP_xderef_size {}", size),
+                false => format!("DW_OP_deref_size {}", size),
+            },
+            Operation::CallFrameCFA => "DW_OP_call_frame_cfa".to_string(),
+            Operation::StackValue => "DW_OP_stack_value".to_string(),
+            Operation::TLS => "DW_OP_form_tls_address".to_string(),
+            Operation::Plus => "DW_OP_plus".to_string(),
+            Operation::Minus => "DW_OP_minus".to_string(),
+            Operation::And => "DW_OP_and".to_string(),
+            Operation::Or => "DW_OP_or".to_string(),
+            Operation::Drop => "DW_OP_drop".to_string(),
+            Operation::Nop => "DW_OP_nop".to_string(),
+            Operation::ImplicitValue { data } => {
+                format!(
+                    "DW_OP_implicit_value {}",
+                    format_bytes(data.to_slice().as_deref().unwrap_or(&[]))
+                )
+            }
+            Operation::EntryValue { expression } => format!(
+                "DW_OP_entry_value({})",
+                format_expression(Expression(expression), encoding, arch)
+            ),
+            other => format!("{:?}", other),
+        });
+    }
+
+    if parts.is_empty() {
+        "<empty>".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
@@ -1,20 +1,30 @@
+use crate::cfi::FrameTable;
+use crate::debug_files::PathRemap;
+use crate::debug_map::DebugMap;
+use crate::line_table::{source_entries, LineFlagIndex, SourceEntry};
+use crate::pdb::PdbInfo;
+use crate::relocatable::SectionLayout;
+use crate::symbols::SymbolIndex;
+use crate::variables::VariableIndex;
 use object::pe::IMAGE_SCN_MEM_EXECUTE;
 use object::{Object, ObjectSection, SectionFlags};
 use once_cell::unsync::OnceCell;
+use std::borrow::Cow;
 use std::collections::btree_map;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Arc;
 
-use addr2line::Context;
+use addr2line::{Context, Location};
 use capstone::arch::{arm, arm64, x86};
 use capstone::prelude::*;
 use fallible_iterator::FallibleIterator;
 use gimli::RunTimeEndian;
-use gimli::{read::Dwarf, EndianSlice, SectionId};
+use gimli::{read::Dwarf, DwarfPackage, EndianSlice, SectionId};
 use std::error::Error;
+use typed_arena::Arena;
 // pub type LineMap = BTreeMap<u32,Vec<InstructionDetail>>;
 // pub type FileMap = HashMap<Arc<Path>,LineMap>;
 
@@ -22,6 +32,8 @@ use std::error::Error;
 pub struct CodeRange<'a> {
     pub address: u64,
     pub data: &'a [u8],
+    /// column of the line table row, 0 when it has none (as in DWARF)
+    pub column: u32,
 }
 
 #[derive(Debug, Default)]
@@ -48,20 +60,108 @@ impl<'a> FileMap<'a> {
     pub fn get(&self, id: &Arc<Path>) -> Option<&LineMap<'a>> {
         self.inner.get(id)
     }
+
+    /// every source file with a line in the map
+    pub fn files(&self) -> impl Iterator<Item = &Arc<Path>> {
+        self.inner.keys()
+    }
 }
 
 pub type EStr<'a> = EndianSlice<'a, RunTimeEndian>;
+/// (address, size, location) of one line table row
+pub type LocationRow<'a> = (u64, u64, Location<'a>);
+
+/// A separate object file whose debug sections stand in for the main one's
+pub struct DebugFile<'a> {
+    pub path: Arc<Path>,
+    pub obj: object::File<'a>,
+}
 
 // #[derive(Debug)]
 pub struct MachineFile<'a> {
     pub obj: object::File<'a>,
     pub sections: Box<[Section<'a>]>,
+    pub debug_file: Option<DebugFile<'a>>,
+    /// dwz supplementary file (`.gnu_debugaltlink`) shared between several debug files
+    pub sup_file: Option<DebugFile<'a>>,
+    /// `<bin>.dwp` holding the packaged .dwo units of a split-DWARF build
+    pub dwp_path: Option<Arc<Path>>,
+    /// section placement when this is a relocatable object
+    pub layout: SectionLayout,
+    /// the static library (or universal binary) this object is a member of
+    pub archive: Option<Arc<Path>>,
+    /// mach-o STABS map to the .o files holding the DWARF
+    pub debug_map: Option<Arc<DebugMap<'a>>>,
+    /// line and function info of a PE file built by MSVC
+    pub pdb: Option<Arc<PdbInfo>>,
+    /// why the PDB found for this PE file could not be read
+    pub pdb_error: Option<Box<str>>,
+    /// rewrites the source paths in the DWARF to local ones
+    pub source_remap: Rc<PathRemap>,
+    files_arena: &'a Arena<Vec<u8>>,
     dwarf: OnceCell<Arc<Dwarf<EStr<'a>>>>,
+    dwarf_package: OnceCell<Option<DwarfPackage<EStr<'a>>>>,
     addr2line: OnceCell<Arc<Context<EStr<'a>>>>,
     file_lines: OnceCell<Arc<FileMap<'a>>>, //line -> instruction>
+    variables: OnceCell<Rc<VariableIndex<'a>>>,
+    line_flags: OnceCell<Arc<LineFlagIndex>>,
+    source_entries: OnceCell<Arc<HashMap<PathBuf, SourceEntry>>>,
+    symbols: OnceCell<Arc<SymbolIndex>>,
+    frames: OnceCell<Option<FrameTable<'a>>>,
     capstone: OnceCell<Rc<Capstone>>,
 }
 
+/// Finds the debug info for a binary address: the file's DWARF, its debug map or its PDB
+#[derive(Clone)]
+pub struct DebugLocator<'a> {
+    ctx: Arc<Context<EStr<'a>>>,
+    debug_map: Option<Arc<DebugMap<'a>>>,
+    pdb: Option<Arc<PdbInfo>>,
+}
+
+impl<'a> DebugLocator<'a> {
+    /// the context holding `address` and the address to ask it for
+    pub fn locate(&self, address: u64) -> Option<(&Context<EStr<'a>>, u64)> {
+        match &self.debug_map {
+            Some(map) => map.locate(address),
+            None => Some((&self.ctx, address)),
+        }
+    }
+
+    /// the PDB standing in for DWARF, function names come from here when it is set
+    pub fn pdb(&self) -> Option<&PdbInfo> {
+        self.pdb.as_deref()
+    }
+
+    pub fn find_location(&self, address: u64) -> Result<Option<Location<'_>>, Box<dyn Error>> {
+        if let Some(pdb) = &self.pdb {
+            return Ok(pdb.find_location(address));
+        }
+        match self.locate(address) {
+            Some((ctx, probe)) => Ok(ctx.find_location(probe)?),
+            None => Ok(None),
+        }
+    }
+
+    /// line rows overlapping `low..high` as (address, size, location)
+    pub fn find_location_range(
+        &self,
+        low: u64,
+        high: u64,
+    ) -> Result<Vec<LocationRow<'_>>, Box<dyn Error>> {
+        if let Some(pdb) = &self.pdb {
+            return Ok(pdb.find_location_range(low, high));
+        }
+        match &self.debug_map {
+            Some(map) => map.find_location_range(low, high),
+            None => {
+                let rows = self.ctx.find_location_range(low, high)?;
+                Ok(FallibleIterator::collect(rows)?)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Section<'a> {
     Code(CodeSection<'a>),
@@ -94,7 +194,7 @@ fn dissasm(
 ) -> Result<Arc<[InstructionDetail]>, Box<dyn Error>> {
     let disasm = cs.disasm_all(data, address)?;
     let mut instructions = Vec::new();
-    for (_serial_number, insn) in disasm.iter().enumerate() {
+    for insn in disasm.iter() {
         instructions.push(insn.into());
     }
     Ok(instructions.into())
@@ -120,7 +220,7 @@ pub fn map_dissasm(
         cur_data = &cur_data[(end - cur_address) as usize..];
         cur_address = end;
 
-        for (_serial_number, insn) in disasm.iter().enumerate() {
+        for insn in disasm.iter() {
             f(insn.into())?;
         }
     }
@@ -142,7 +242,7 @@ impl CodeSection<'_> {
     ) -> Result<Arc<[InstructionDetail]>, Box<dyn Error>> {
         if let Some(ans) = self.asm.get() {
             for ins in ans.iter() {
-                f(&ins)?;
+                f(ins)?;
             }
 
             return Ok(ans.clone());
@@ -150,7 +250,7 @@ impl CodeSection<'_> {
         self.asm
             .get_or_try_init(|| {
                 let mut instructions = Vec::new();
-                map_dissasm(&cs, self.data, self.address, &mut |ins| {
+                map_dissasm(cs, self.data, self.address, &mut |ins| {
                     f(&ins)?;
                     instructions.push(ins);
                     Ok(())
@@ -160,9 +260,30 @@ impl CodeSection<'_> {
             .cloned()
     }
 
+    /// The operands of `ins` disassembled as if it sat at `shown`,
+    /// so pc relative targets move along with a load bias
+    pub fn shown_op_str(
+        &self,
+        cs: &Capstone,
+        ins: &InstructionDetail,
+        shown: u64,
+    ) -> Result<Box<str>, Box<dyn Error>> {
+        let start = ins.address.wrapping_sub(self.address) as usize;
+        let data = start
+            .checked_add(ins.size)
+            .and_then(|end| self.data.get(start..end));
+        let Some(data) = data.filter(|_| shown != ins.address) else {
+            return Ok(ins.op_str.clone());
+        };
+        let disasm = cs.disasm_count(data, shown, 1)?;
+        Ok(disasm.first().map_or(ins.op_str.clone(), |insn| {
+            insn.op_str().unwrap_or("unknown").into()
+        }))
+    }
+
     pub fn get_asm(&self, cs: &Capstone) -> Result<Arc<[InstructionDetail]>, Box<dyn Error>> {
         self.asm
-            .get_or_try_init(|| dissasm(&cs, self.data, self.address))
+            .get_or_try_init(|| dissasm(cs, self.data, self.address))
             .cloned()
     }
 }
@@ -202,6 +323,16 @@ impl InstructionDetail {
 }
 
 impl<'a> MachineFile<'a> {
+    /// true if one of the code sections holds `address`
+    pub fn has_code_at(&self, address: u64) -> bool {
+        self.sections.iter().any(|s| match s {
+            Section::Code(code) => {
+                code.address <= address && address < code.address + code.data.len() as u64
+            }
+            Section::Info(_) => false,
+        })
+    }
+
     pub fn dissasm_address(
         &self,
         target: u64,
@@ -222,10 +353,27 @@ impl<'a> MachineFile<'a> {
         }
         Ok(None)
     }
+    /// `CodeSection::shown_op_str` for whichever code section holds `ins`
+    pub fn shown_op_str(
+        &self,
+        ins: &InstructionDetail,
+        shown: u64,
+    ) -> Result<Box<str>, Box<dyn Error>> {
+        for s in &self.sections {
+            let Section::Code(code) = s else {
+                continue;
+            };
+            if code.address <= ins.address && ins.address <= code.get_high() {
+                return code.shown_op_str(&*self.get_capstone()?, ins, shown);
+            }
+        }
+        Ok(ins.op_str.clone())
+    }
+
     pub fn get_lines_map(&self) -> Result<Arc<FileMap<'a>>, Box<dyn Error>> {
         self.file_lines
             .get_or_try_init(|| {
-                let context = self.get_addr2line()?;
+                let locator = self.get_locator()?;
 
                 // let mut ans = Arc::new(HashMap::new());
                 let mut ans = Arc::new(FileMap::default());
@@ -237,11 +385,10 @@ impl<'a> MachineFile<'a> {
                     };
 
                     let end_address = code_section.get_high();
-                    let mut iter =
-                        context.find_location_range(code_section.address, end_address)?;
+                    let rows = locator.find_location_range(code_section.address, end_address)?;
                     let mut prev_end = code_section.address;
 
-                    while let Some((low, size, loc)) = FallibleIterator::next(&mut iter)? {
+                    for (low, size, loc) in rows {
                         // if low != prev_end{
                         if low > prev_end {
                             let size = (low - prev_end) as usize;
@@ -250,6 +397,7 @@ impl<'a> MachineFile<'a> {
                             handle.extra.push(CodeRange {
                                 address: prev_end,
                                 data,
+                                column: 0,
                             })
                         }
 
@@ -263,11 +411,15 @@ impl<'a> MachineFile<'a> {
 
                         let data = &code_section.data[start_idx..][..size as usize];
 
-                        let cur_range = CodeRange { address: low, data };
+                        let cur_range = CodeRange {
+                            address: low,
+                            data,
+                            column: loc.column.unwrap_or(0),
+                        };
 
                         match (loc.file, loc.line) {
                             (Some(file_name), Some(line)) => {
-                                let file = Path::new(file_name).into();
+                                let file = self.source_path(file_name).into();
 
                                 handle
                                     .inner
@@ -279,7 +431,7 @@ impl<'a> MachineFile<'a> {
                                     .push(cur_range);
                             }
                             (Some(file_name), None) => {
-                                let file = Path::new(file_name).into();
+                                let file = self.source_path(file_name).into();
 
                                 handle.inner.entry(file).or_default().extra.push(cur_range);
                             }
@@ -292,45 +444,250 @@ impl<'a> MachineFile<'a> {
             .cloned()
     }
 
-    fn get_gimli_section(&self, section: SectionId) -> &'a [u8] {
-        self.obj
-            .section_by_name(section.name())
-            .and_then(|x| x.data().ok())
-            .unwrap_or(&[])
+    /// where a file name from the line table lives on this machine
+    pub fn source_path<'p>(&self, file_name: &'p str) -> Cow<'p, Path> {
+        self.source_remap.apply(Path::new(file_name))
+    }
+
+    /// true if the file itself carries DWARF (as opposed to a stripped binary)
+    pub fn has_dwarf(&self) -> bool {
+        self.obj.section_by_name(".debug_info").is_some()
+    }
+
+    /// Use the DWARF of another file (from build-id or .gnu_debuglink) for this one
+    pub fn set_debug_file(
+        &mut self,
+        path: Arc<Path>,
+        data: &'a [u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let obj = object::File::parse(data)?;
+        self.debug_file = Some(DebugFile { path, obj });
+
+        //anything derived from the old debug info is stale now
+        self.dwarf = OnceCell::new();
+        self.addr2line = OnceCell::new();
+        self.file_lines = OnceCell::new();
+        self.variables = OnceCell::new();
+        self.line_flags = OnceCell::new();
+        self.source_entries = OnceCell::new();
+        self.symbols = OnceCell::new();
+        self.frames = OnceCell::new();
+        Ok(())
     }
 
-    pub fn load_dwarf(&self) -> Result<Arc<Dwarf<EStr<'a>>>, gimli::Error> {
+    /// Resolve `DW_FORM_GNU_strp_alt`/`ref_alt` against this file
+    pub fn set_sup_file(&mut self, path: Arc<Path>, data: &'a [u8]) -> Result<(), Box<dyn Error>> {
+        let obj = object::File::parse(data)?;
+        self.sup_file = Some(DebugFile { path, obj });
+
+        self.dwarf = OnceCell::new();
+        self.addr2line = OnceCell::new();
+        self.file_lines = OnceCell::new();
+        self.variables = OnceCell::new();
+        self.line_flags = OnceCell::new();
+        self.source_entries = OnceCell::new();
+        Ok(())
+    }
+
+    /// the object debug sections are read from
+    pub fn dwarf_obj(&self) -> &object::File<'a> {
+        match &self.debug_file {
+            Some(debug) => &debug.obj,
+            None => &self.obj,
+        }
+    }
+
+    fn get_gimli_section(&self, section: SectionId) -> Result<&'a [u8], Box<dyn Error>> {
+        let data = section_data(self.dwarf_obj(), section.name(), self.files_arena)
+            .map_err(|e| format!("failed to read {}: {}", section.name(), e))?;
+
+        //an object's DWARF points at its sections through relocations
+        if self.debug_file.is_some() {
+            return Ok(data);
+        }
+        Ok(self
+            .layout
+            .relocate(&self.obj, section.name(), data, self.files_arena))
+    }
+
+    pub fn load_dwarf(&self) -> Result<Arc<Dwarf<EStr<'a>>>, Box<dyn Error>> {
         self.dwarf
             .get_or_try_init(|| {
-                let endian = if self.obj.is_little_endian() {
-                    RunTimeEndian::Little
-                } else {
-                    RunTimeEndian::Big
-                };
-                Dwarf::load(
-                    |section| -> Result<EndianSlice<RunTimeEndian>, gimli::Error> {
-                        Ok(EndianSlice::new(self.get_gimli_section(section), endian))
+                let endian = obj_endian(self.dwarf_obj());
+                let mut dwarf = Dwarf::load(
+                    |section| -> Result<EndianSlice<RunTimeEndian>, Box<dyn Error>> {
+                        Ok(EndianSlice::new(self.get_gimli_section(section)?, endian))
                     },
-                )
-                .map(Arc::new)
+                )?;
+
+                if let Some(sup) = &self.sup_file {
+                    dwarf.load_sup(|section| -> Result<EStr<'a>, Box<dyn Error>> {
+                        let data = section_data(&sup.obj, section.name(), self.files_arena)?;
+                        Ok(EndianSlice::new(data, endian))
+                    })?;
+                }
+
+                Ok(Arc::new(dwarf))
             })
             .cloned()
     }
 
+    /// true if `dwarf` is the debug info this file handed to addr2line
+    pub fn owns_dwarf(&self, dwarf: &Dwarf<EStr<'_>>) -> bool {
+        self.dwarf
+            .get()
+            .is_some_and(|d| std::ptr::addr_eq(Arc::as_ptr(d), dwarf))
+    }
+
+    /// Lazily reads the .dwp next to this file, None if there is none or it is broken
+    pub fn get_dwarf_package(&self) -> Option<&DwarfPackage<EStr<'a>>> {
+        self.dwarf_package
+            .get_or_init(|| {
+                let path = self.dwp_path.as_ref()?;
+                let data: &'a [u8] = self.files_arena.alloc(std::fs::read(path).ok()?);
+                let obj = object::File::parse(data).ok()?;
+                let endian = obj_endian(&obj);
+
+                DwarfPackage::load(
+                    |section| -> Result<EStr<'a>, Box<dyn Error>> {
+                        let data = match section.dwo_name() {
+                            Some(name) => section_data(&obj, name, self.files_arena)?,
+                            None => &[],
+                        };
+                        Ok(EndianSlice::new(data, endian))
+                    },
+                    EndianSlice::new(&[], endian),
+                )
+                .ok()
+            })
+            .as_ref()
+    }
+
     pub fn get_addr2line(&self) -> Result<Arc<Context<EStr<'a>>>, Box<dyn Error>> {
         self.addr2line
             .get_or_try_init(|| Ok(Context::from_arc_dwarf(self.load_dwarf()?)?.into()))
             .cloned()
     }
 
+    /// The symbol table, taken from the debug file when the binary itself has none
+    pub fn get_symbols(&self) -> Arc<SymbolIndex> {
+        self.symbols
+            .get_or_init(|| {
+                let index = SymbolIndex::new(&self.obj, &self.layout);
+                match &self.debug_file {
+                    Some(debug) if index.is_empty() => {
+                        SymbolIndex::new(&debug.obj, &SectionLayout::default()).into()
+                    }
+                    _ => index.into(),
+                }
+            })
+            .clone()
+    }
+
+    pub fn get_locator(&self) -> Result<DebugLocator<'a>, Box<dyn Error>> {
+        Ok(DebugLocator {
+            ctx: self.get_addr2line()?,
+            debug_map: self.debug_map.clone(),
+            pdb: self.pdb.clone(),
+        })
+    }
+
+    /// where local variables live at each address, see [`VariableIndex`]
+    pub fn get_variables(&self) -> Result<Rc<VariableIndex<'a>>, Box<dyn Error>> {
+        self.variables
+            .get_or_try_init(|| {
+                let index = VariableIndex::new(self.load_dwarf()?, &self.obj, self.get_frames())?;
+                Ok(index.into())
+            })
+            .cloned()
+    }
+
+    /// is_stmt, prologue/epilogue markers and discriminators of the line table rows
+    pub fn get_line_flags(&self) -> Result<Arc<LineFlagIndex>, Box<dyn Error>> {
+        self.line_flags
+            .get_or_try_init(|| Ok(LineFlagIndex::new(&*self.load_dwarf()?)?.into()))
+            .cloned()
+    }
+
+    /// what the line tables record about the source file at `path` (after remapping)
+    pub fn get_source_entry(&self, path: &Path) -> Option<SourceEntry> {
+        //broken DWARF counts as no entries, so the line tables are only walked once
+        let entries = self.source_entries.get_or_init(|| {
+            self.load_dwarf()
+                .and_then(|dwarf| {
+                    source_entries(&dwarf, |name| self.source_path(name).into_owned())
+                })
+                .unwrap_or_default()
+                .into()
+        });
+        entries.get(path).cloned()
+    }
+
+    /// the unwind tables, `None` when the file has neither `.eh_frame` nor `.debug_frame`
+    pub fn get_frames(&self) -> Option<FrameTable<'a>> {
+        self.frames
+            .get_or_init(|| {
+                //an object's FDEs point at its code through relocations, like its DWARF
+                let eh_frame = self.obj.section_by_name(".eh_frame").and_then(|_| {
+                    let data = section_data(&self.obj, ".eh_frame", self.files_arena).ok()?;
+                    Some(
+                        self.layout
+                            .relocate(&self.obj, ".eh_frame", data, self.files_arena),
+                    )
+                });
+                let debug_frame = match self.get_gimli_section(SectionId::DebugFrame) {
+                    Ok(data) if !data.is_empty() => Some(data),
+                    //stripping can leave it in the binary instead of the debug file
+                    _ => section_data(&self.obj, ".debug_frame", self.files_arena)
+                        .ok()
+                        .filter(|data| !data.is_empty()),
+                };
+                FrameTable::new(&self.obj, &self.layout, eh_frame, debug_frame)
+            })
+            .clone()
+    }
+
+    /// Read lines and functions from a PDB instead of DWARF
+    pub fn set_pdb(&mut self, pdb: PdbInfo) {
+        self.pdb = Some(pdb.into());
+        self.file_lines = OnceCell::new();
+    }
+
+    /// addresses of the sections in file order, which is what PDB section numbers count
+    pub fn section_addresses(&self) -> Vec<u64> {
+        self.obj
+            .sections()
+            .map(|s| self.layout.section_address(&s))
+            .collect()
+    }
+
+    /// Read line info from the objects of a debug map instead of the (empty) DWARF of this file
+    pub fn set_debug_map(&mut self, debug_map: DebugMap<'a>) {
+        self.debug_map = Some(debug_map.into());
+        self.file_lines = OnceCell::new();
+    }
+
     pub fn get_capstone(&self) -> Result<Rc<Capstone>, Box<dyn Error>> {
         self.capstone
             .get_or_try_init(|| Ok(create_capstone(self.obj.architecture())?.into()))
             .cloned()
     }
 
-    pub fn parse(buffer: &'a [u8]) -> Result<MachineFile<'a>, Box<dyn Error>> {
+    pub fn parse(
+        buffer: &'a [u8],
+        files_arena: &'a Arena<Vec<u8>>,
+    ) -> Result<MachineFile<'a>, Box<dyn Error>> {
+        Self::parse_at(buffer, files_arena, 0)
+    }
+
+    /// Like parse but a relocatable object gets its sections placed from `base` on
+    pub fn parse_at(
+        buffer: &'a [u8],
+        files_arena: &'a Arena<Vec<u8>>,
+        base: u64,
+    ) -> Result<MachineFile<'a>, Box<dyn Error>> {
         let obj = object::File::parse(buffer)?;
+        let layout = SectionLayout::new_at(&obj, base);
         let mut parsed_sections = Vec::new();
 
         for section in obj.sections() {
@@ -341,7 +698,7 @@ impl<'a> MachineFile<'a> {
                 parsed_sections.push(Section::Code(CodeSection {
                     name: section_name,
                     data: section_data,
-                    address: section.address(),
+                    address: layout.section_address(&section),
                     asm: OnceCell::new(),
                 }));
             } else {
@@ -349,7 +706,7 @@ impl<'a> MachineFile<'a> {
                 parsed_sections.push(Section::Info(InfoSection {
                     name: section_name,
                     data: section_data,
-                    address: section.address(),
+                    address: layout.section_address(&section),
                 }));
             }
         }
@@ -357,9 +714,25 @@ impl<'a> MachineFile<'a> {
         let ans = MachineFile {
             obj,
             sections: parsed_sections.into(),
+            layout,
+            archive: None,
+            debug_file: None,
+            sup_file: None,
+            dwp_path: None,
+            debug_map: None,
+            pdb: None,
+            pdb_error: None,
+            source_remap: Rc::default(),
+            files_arena,
             dwarf: OnceCell::new(),
+            dwarf_package: OnceCell::new(),
             addr2line: OnceCell::new(),
             file_lines: OnceCell::new(),
+            variables: OnceCell::new(),
+            line_flags: OnceCell::new(),
+            source_entries: OnceCell::new(),
+            symbols: OnceCell::new(),
+            frames: OnceCell::new(),
             capstone: OnceCell::new(),
         };
 
@@ -367,6 +740,33 @@ impl<'a> MachineFile<'a> {
     }
 }
 
+fn obj_endian(obj: &object::File) -> RunTimeEndian {
+    if obj.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    }
+}
+
+/// Section contents by name, missing sections are empty.
+///
+/// Compressed sections (SHF_COMPRESSED and the older .zdebug_*) are inflated into the arena
+/// so the data still lives as long as the file.
+fn section_data<'a>(
+    obj: &object::File<'a>,
+    name: &str,
+    files_arena: &'a Arena<Vec<u8>>,
+) -> Result<&'a [u8], object::Error> {
+    let Some(section) = obj.section_by_name(name) else {
+        return Ok(&[]);
+    };
+
+    Ok(match section.uncompressed_data()? {
+        Cow::Borrowed(data) => data,
+        Cow::Owned(data) => files_arena.alloc(data),
+    })
+}
+
 // #[inline(always)]
 // fn slow_compile(ans: &mut MachineFile, arch: Architecture) -> Result<(), Box<dyn Error>> {
 //     let cs = create_capstone(arch)?;
@@ -390,6 +790,68 @@ impl<'a> MachineFile<'a> {
 //     }
 // }
 
+/// (arch name, data) of one slice in a universal binary
+pub type FatSlice<'a> = (String, &'a [u8]);
+
+/// The slices of a universal (fat) Mach-O file, None for any other file
+pub fn fat_slices(data: &[u8]) -> Result<Option<Vec<FatSlice<'_>>>, Box<dyn Error>> {
+    use object::read::macho::{FatArch, MachOFatFile32, MachOFatFile64};
+
+    fn collect<'d, A: FatArch>(
+        arches: &[A],
+        data: &'d [u8],
+    ) -> Result<Vec<FatSlice<'d>>, Box<dyn Error>> {
+        arches
+            .iter()
+            .map(|arch| {
+                Ok((
+                    slice_name(arch.cputype(), arch.cpusubtype()),
+                    arch.data(data)?,
+                ))
+            })
+            .collect()
+    }
+
+    match object::FileKind::parse(data)? {
+        object::FileKind::MachOFat32 => {
+            Ok(Some(collect(MachOFatFile32::parse(data)?.arches(), data)?))
+        }
+        object::FileKind::MachOFat64 => {
+            Ok(Some(collect(MachOFatFile64::parse(data)?.arches(), data)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// the names lipo uses for a slice
+fn slice_name(cputype: u32, cpusubtype: u32) -> String {
+    use object::macho::*;
+
+    let name = match (cputype, cpusubtype & !CPU_SUBTYPE_MASK) {
+        (CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_H) => "x86_64h",
+        (CPU_TYPE_X86_64, _) => "x86_64",
+        (CPU_TYPE_X86, _) => "i386",
+        (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64E) => "arm64e",
+        (CPU_TYPE_ARM64, _) => "arm64",
+        (CPU_TYPE_ARM64_32, _) => "arm64_32",
+        (CPU_TYPE_ARM, _) => "arm",
+        (CPU_TYPE_POWERPC64, _) => "ppc64",
+        (CPU_TYPE_POWERPC, _) => "ppc",
+        _ => return format!("cputype{}", cputype),
+    };
+    name.to_string()
+}
+
+/// `--arch` accepts the names other tools use too (aarch64, amd64)
+pub fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "aarch64" => "arm64",
+        "amd64" | "x86-64" => "x86_64",
+        "x86" | "i686" => "i386",
+        arch => arch,
+    }
+}
+
 pub fn create_capstone(arch: object::Architecture) -> Result<Capstone, Box<dyn Error>> {
     let mut cs = match arch {
         object::Architecture::X86_64 => Capstone::new()
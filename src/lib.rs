@@ -1,7 +1,20 @@
 pub mod args;
+pub mod cfi;
 pub mod config;
+pub mod core_dump;
+pub mod debug_files;
+pub mod debug_map;
+pub mod dwarf_dump;
 pub mod errors;
 pub mod file_parser;
+pub mod line_table;
+pub mod log_scan;
+pub mod md5;
+pub mod pdb;
 pub mod program_context;
+pub mod relocatable;
 pub mod subcommands;
+pub mod symbols;
+pub mod types;
+pub mod variables;
 pub mod walk;
@@ -0,0 +1,187 @@
+use crate::file_parser::EStr;
+use gimli::Dwarf;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The parts of a line table row that `(file, line)` leaves out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineFlags {
+    /// a place a debugger would stop for the line, off for rows in the middle of a statement
+    pub is_stmt: bool,
+    pub prologue_end: bool,
+    pub epilogue_begin: bool,
+    /// tells apart blocks that share a line, like the copies of an unrolled loop body
+    pub discriminator: u64,
+}
+
+/// a plain statement row, also used for addresses the line table does not cover
+impl Default for LineFlags {
+    fn default() -> Self {
+        LineFlags {
+            is_stmt: true,
+            prologue_end: false,
+            epilogue_begin: false,
+            discriminator: 0,
+        }
+    }
+}
+
+impl fmt::Display for LineFlags {
+    /// only the markers that are set, `is_stmt` is the common case and is left out
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.prologue_end {
+            parts.push("prologue_end".to_string());
+        }
+        if self.epilogue_begin {
+            parts.push("epilogue_begin".to_string());
+        }
+        if self.discriminator != 0 {
+            parts.push(format!("discriminator={}", self.discriminator));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Every line table row of a file with its flags, sorted by address
+#[derive(Debug, Default)]
+pub struct LineFlagIndex {
+    /// None where a sequence ends
+    rows: Vec<(u64, Option<LineFlags>)>,
+}
+
+impl LineFlagIndex {
+    pub fn new(dwarf: &Dwarf<EStr<'_>>) -> Result<Self, Box<dyn Error>> {
+        let mut rows = Vec::new();
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut program_rows = program.rows();
+            while let Some((_, row)) = program_rows.next_row()? {
+                let flags = (!row.end_sequence()).then(|| LineFlags {
+                    is_stmt: row.is_stmt(),
+                    prologue_end: row.prologue_end(),
+                    epilogue_begin: row.epilogue_begin(),
+                    discriminator: row.discriminator(),
+                });
+                rows.push((row.address(), flags));
+            }
+        }
+        rows.sort_by_key(|(address, _)| *address);
+
+        //rows sharing an address describe the same instruction, the last one wins but keeps the flags
+        let mut merged: Vec<(u64, Option<LineFlags>)> = Vec::with_capacity(rows.len());
+        for (address, flags) in rows {
+            match merged.last_mut() {
+                Some((prev_address, prev)) if *prev_address == address => {
+                    *prev = match (*prev, flags) {
+                        (Some(prev), Some(mut flags)) => {
+                            flags.is_stmt |= prev.is_stmt;
+                            flags.prologue_end |= prev.prologue_end;
+                            flags.epilogue_begin |= prev.epilogue_begin;
+                            Some(flags)
+                        }
+                        //another sequence starting where one ended
+                        (prev, None) => prev,
+                        (None, flags) => flags,
+                    };
+                }
+                _ => merged.push((address, flags)),
+            }
+        }
+        Ok(LineFlagIndex { rows: merged })
+    }
+
+    /// The flags of the row holding `address`, the markers only count on the row's first instruction
+    pub fn flags_at(&self, address: u64) -> Option<LineFlags> {
+        let idx = self
+            .rows
+            .partition_point(|(start, _)| *start <= address)
+            .checked_sub(1)?;
+        let (start, flags) = self.rows[idx];
+        let mut flags = flags?;
+        if start != address {
+            flags.prologue_end = false;
+            flags.epilogue_begin = false;
+        }
+        Some(flags)
+    }
+}
+
+/// What the line table knows about a source file besides its name
+#[derive(Debug, Clone, Default)]
+pub struct SourceEntry {
+    /// the text itself when the compiler embedded it (clang's `-gembed-source`, DW_LNCT_LLVM_source)
+    pub source: Option<Arc<str>>,
+    /// DWARF 5 checksum of the file as it was compiled
+    pub md5: Option<[u8; 16]>,
+    /// `md5` came from a GCC unit, GCC leaves writing it to GNU as
+    pub md5_from_gcc: bool,
+}
+
+/// The file entries of every line table, keyed by the path `resolve` turns their names into
+pub fn source_entries(
+    dwarf: &Dwarf<EStr<'_>>,
+    resolve: impl Fn(&str) -> PathBuf,
+) -> Result<HashMap<PathBuf, SourceEntry>, Box<dyn Error>> {
+    let mut ans: HashMap<PathBuf, SourceEntry> = HashMap::new();
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        let unit = dwarf.unit(header)?;
+        let Some(program) = &unit.line_program else {
+            continue;
+        };
+        let header = program.header();
+        let from_gcc = producer(dwarf, &unit).is_some_and(|p| p.starts_with("GNU "));
+        for file in header.file_names() {
+            //the same layout addr2line uses: comp dir, then include dir, then the name
+            let mut path = PathBuf::new();
+            if let Some(comp_dir) = unit.comp_dir {
+                path.push(&*comp_dir.to_string_lossy());
+            }
+            if let Some(dir) = file.directory(header) {
+                path.push(&*dwarf.attr_string(&unit, dir)?.to_string_lossy());
+            }
+            path.push(
+                &*dwarf
+                    .attr_string(&unit, file.path_name())?
+                    .to_string_lossy(),
+            );
+
+            //files clang had nothing to embed for get an empty string
+            let source = match file.source() {
+                Some(source) => Some(dwarf.attr_string(&unit, source)?)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string_lossy().into()),
+                None => None,
+            };
+
+            let md5 = header.file_has_md5().then(|| *file.md5());
+
+            let entry = ans.entry(resolve(&path.to_string_lossy())).or_default();
+            if entry.source.is_none() {
+                entry.source = source;
+            }
+            if entry.md5.is_none() {
+                entry.md5 = md5;
+                entry.md5_from_gcc = from_gcc;
+            }
+        }
+    }
+    Ok(ans)
+}
+
+/// DW_AT_producer of the unit, like `GNU C17 12.2.0 -g` or `clang version 17.0.6`
+fn producer(dwarf: &Dwarf<EStr<'_>>, unit: &gimli::Unit<EStr<'_>>) -> Option<String> {
+    let mut entries = unit.entries();
+    let (_, root) = entries.next_dfs().ok()??;
+    let value = root.attr_value(gimli::constants::DW_AT_producer).ok()??;
+    let producer = dwarf.attr_string(unit, value).ok()?;
+    Some(producer.to_string_lossy().into_owned())
+}
@@ -0,0 +1,151 @@
+/// A code address mentioned in a log line
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogAddress {
+    /// `(bin+0x1a2b)` from sanitizers, `bin(+0x1a2b)` from glibc's backtrace_symbols
+    Module { module: String, offset: u64 },
+    /// a bare runtime address like `0x55d1...`
+    Raw(u64),
+}
+
+/// Every hex address in `line`, in the order they appear
+pub fn scan_line(line: &str) -> Vec<LogAddress> {
+    let bytes = line.as_bytes();
+    let mut ans = Vec::new();
+    let mut start = 0;
+    while let Some(found) = line[start..].find("0x") {
+        let pos = start + found;
+        let (value, end) = read_hex(bytes, pos + 2);
+        start = end;
+        let Some(value) = value else {
+            continue;
+        };
+
+        match pos.checked_sub(1).map(|prev| bytes[prev]) {
+            Some(b'+') => {
+                if let Some(module) = module_before(line, pos - 1) {
+                    ans.push(LogAddress::Module {
+                        module,
+                        offset: value,
+                    });
+                }
+            }
+            //the tail of some identifier, not an address
+            Some(c) if c.is_ascii_alphanumeric() || c == b'_' => {}
+            _ => ans.push(LogAddress::Raw(value)),
+        }
+    }
+    ans
+}
+
+/// The `#3` sanitizers and gdb start a backtrace line with
+pub fn frame_number(line: &str) -> Option<u64> {
+    let rest = line.trim_start().strip_prefix('#')?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// the hex number at `start` and where it ends, None if it is empty or runs into a word
+fn read_hex(bytes: &[u8], start: usize) -> (Option<u64>, usize) {
+    let end = bytes[start..]
+        .iter()
+        .position(|b| !b.is_ascii_hexdigit())
+        .map_or(bytes.len(), |len| start + len);
+    if end == start
+        || bytes
+            .get(end)
+            .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+    {
+        return (None, end);
+    }
+    let digits = std::str::from_utf8(&bytes[start..end]).unwrap();
+    (u64::from_str_radix(digits, 16).ok(), end)
+}
+
+/// the module name ending right before the `+` at `plus`
+fn module_before(line: &str, plus: usize) -> Option<String> {
+    let head = &line[..plus];
+    let module = last_word(head);
+    if !module.is_empty() {
+        return Some(module.to_string());
+    }
+
+    //glibc puts the module before the parens: ./bin(+0x1a2b)
+    let module = last_word(head.strip_suffix('(')?);
+    (!module.is_empty()).then(|| module.to_string())
+}
+
+fn last_word(head: &str) -> &str {
+    let start = head
+        .rfind(|c: char| c.is_whitespace() || matches!(c, '(' | '[' | '<' | '\'' | '"'))
+        .map_or(0, |i| i + 1);
+    &head[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(module: &str, offset: u64) -> LogAddress {
+        LogAddress::Module {
+            module: module.to_string(),
+            offset,
+        }
+    }
+
+    #[test]
+    fn sanitizer_frame() {
+        let line = "    #3 0x55d1c0ffee12 in main /src/main.c:10:5 (bin+0x1a2b)";
+        assert_eq!(frame_number(line), Some(3));
+        assert_eq!(
+            scan_line(line),
+            vec![LogAddress::Raw(0x55d1c0ffee12), module("bin", 0x1a2b)]
+        );
+    }
+
+    #[test]
+    fn glibc_backtrace_symbols() {
+        let line = "./bin(+0x1a2b) [0x55d1c0ffee12]";
+        assert_eq!(frame_number(line), None);
+        assert_eq!(
+            scan_line(line),
+            vec![module("./bin", 0x1a2b), LogAddress::Raw(0x55d1c0ffee12)]
+        );
+    }
+
+    #[test]
+    fn identifier_tails_are_not_addresses() {
+        assert_eq!(scan_line("foo0x12 bar_0x34 0x12abcxyz"), vec![]);
+        assert_eq!(
+            scan_line("got foo0x12 at 0x40"),
+            vec![LogAddress::Raw(0x40)]
+        );
+    }
+
+    #[test]
+    fn lines_without_addresses() {
+        assert_eq!(scan_line("all good, exiting"), vec![]);
+        assert_eq!(scan_line("0x"), vec![]);
+        assert_eq!(scan_line("stray + 0x10"), vec![LogAddress::Raw(0x10)]);
+        assert_eq!(frame_number("#"), None);
+        assert_eq!(frame_number("no frame #1"), None);
+    }
+
+    #[test]
+    fn module_names() {
+        let line = "at (libc.so.6+0x29d90)";
+        assert_eq!(
+            module_before(line, line.find('+').unwrap()),
+            Some("libc.so.6".to_string())
+        );
+        let line = "./bin(+0x1a2b)";
+        assert_eq!(
+            module_before(line, line.find('+').unwrap()),
+            Some("./bin".to_string())
+        );
+        //nothing names the module
+        let line = "(+0x10)";
+        assert_eq!(module_before(line, line.find('+').unwrap()), None);
+    }
+}
@@ -1,5 +1,6 @@
 use clap::Parser;
 use source_viewer::args::*;
+use source_viewer::dwarf_dump::DumpFilter;
 use source_viewer::errors::downcast_chain_ref;
 use source_viewer::errors::PrintError;
 use source_viewer::subcommands::*;
@@ -24,23 +25,53 @@ fn main() -> ExitCode {
     apply_color_mode(cli.get_color());
 
     let res = match cli.command {
-        Commands::Walk { opts, file, line } => walk_command(opts.bin.into(), file, line),
-        Commands::Sections { opts } => sections_command(opts.bins),
+        Commands::Walk {
+            opts,
+            file,
+            line,
+            core,
+        } => walk_command(opts.bin.into(), file, line, core, &opts.load),
+        Commands::Sections { opts } => sections_command(opts.bins, &opts.load),
         Commands::Lines {
             opts,
             ignore_unknown,
-        } => lines_command(opts.bins, ignore_unknown),
+            inline_chain,
+            vars,
+            cfa,
+        } => lines_command(
+            opts.bins,
+            ignore_unknown,
+            inline_chain,
+            vars,
+            cfa,
+            &opts.load,
+        ),
         Commands::ViewSource(ViewSource {
             opts,
             all,
             walk,
             selections,
-        }) => view_source_command(&opts.bin, all, walk, selections),
+        }) => view_source_command(&opts.bin, all, walk, selections, &opts.load),
 
-        Commands::Functions { opts } => functions_command(opts.bins),
+        Commands::Functions { opts } => functions_command(opts.bins, &opts.load),
 
-        Commands::ViewSources { opts } => view_sources_command(opts.bins),
-        Commands::DwarfDump { opts } => dwarf_dump_command(opts.bins),
+        Commands::ViewSources { opts } => view_sources_command(opts.bins, &opts.load),
+        Commands::DwarfDump { opts, cu, offset } => dwarf_dump_command(
+            opts.bins,
+            DumpFilter {
+                cu_name: cu,
+                offset,
+            },
+            &opts.load,
+        ),
+        Commands::Cfi { opts } => cfi_command(opts.bins, &opts.load),
+        Commands::Types { opts, name } => types_command(&opts.bin, &name, &opts.load),
+        Commands::Symbolize {
+            opts,
+            addresses,
+            json,
+        } => symbolize_command(&opts.bin, addresses, json, &opts.load),
+        Commands::AnnotateLog { opts } => annotate_log_command(opts.bins, &opts.load),
         Commands::ConfigPaths { .. } => config_paths_command(),
     };
 
@@ -0,0 +1,434 @@
+use crate::file_parser::LocationRow;
+use addr2line::Location;
+use pdb::{FallibleIterator, FileIndex, IdData, IdIndex, LineInfo, LineProgram, SymbolData};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+//lines the compiler hides from debuggers
+const HIDDEN_LINES: [u32; 2] = [0xfeefee, 0xf00f00];
+
+type Res<T> = Result<T, Box<dyn Error>>;
+
+/// The guid a PE file's CodeView record has to match, in the byte order it is stored in
+pub fn pdb_guid(data: &[u8]) -> Res<[u8; 16]> {
+    let mut pdb = pdb::PDB::open(Cursor::new(data))?;
+    Ok(pdb.pdb_information()?.guid.to_bytes_le())
+}
+
+/// Names of the functions inline sites refer to, older PDBs have no IPI stream
+fn function_ids<'s>(pdb: &mut pdb::PDB<'s, Cursor<&'s [u8]>>) -> Res<HashMap<IdIndex, Arc<str>>> {
+    let mut ans = HashMap::new();
+    let Ok(ids) = pdb.id_information() else {
+        return Ok(ans);
+    };
+    let mut iter = ids.iter();
+    while let Some(id) = iter.next()? {
+        let name = match id.parse() {
+            Ok(IdData::Function(f)) => f.name,
+            Ok(IdData::MemberFunction(f)) => f.name,
+            _ => continue,
+        };
+        ans.insert(id.index(), name.to_string().into());
+    }
+    Ok(ans)
+}
+
+/// A line row, possibly belonging to an inlined call
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    address: u64,
+    end: u64,
+    file: u32,
+    line: u32,
+    column: Option<u32>,
+    depth: u32,
+}
+
+/// A range of code that was inlined into a function
+#[derive(Debug, Clone)]
+struct InlineRange {
+    low: u64,
+    high: u64,
+    depth: u32,
+    name: Arc<str>,
+}
+
+/// A function from a module symbol stream
+#[derive(Debug, Clone)]
+pub struct PdbFunction {
+    pub address: u64,
+    pub size: u64,
+    pub name: Box<str>,
+    inlines: Vec<InlineRange>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PdbLine {
+    address: u64,
+    size: u64,
+    file: u32,
+    line: u32,
+    column: Option<u32>,
+}
+
+/// Line and function info read out of a PDB, for PE files built by MSVC (or clang-cl)
+#[derive(Debug)]
+pub struct PdbInfo {
+    pub path: Arc<Path>,
+    files: Box<[Box<str>]>,
+    /// innermost location for every address, sorted
+    lines: Box<[PdbLine]>,
+    /// sorted by address
+    functions: Box<[PdbFunction]>,
+}
+
+/// Everything collected from the modules before the rows get flattened
+struct Builder<'s> {
+    strings: Option<pdb::StringTable<'s>>,
+    func_ids: HashMap<IdIndex, Arc<str>>,
+    section_bases: &'s [u64],
+    files: Vec<Box<str>>,
+    file_index: HashMap<Box<str>, u32>,
+    rows: Vec<Row>,
+    functions: Vec<PdbFunction>,
+}
+
+impl Builder<'_> {
+    fn address(&self, offset: pdb::PdbInternalSectionOffset) -> Option<u64> {
+        let base = self
+            .section_bases
+            .get((offset.section as usize).checked_sub(1)?)?;
+        Some(base + offset.offset as u64)
+    }
+
+    /// global file index for a module's file
+    fn file(&mut self, program: &LineProgram, index: FileIndex) -> Res<u32> {
+        let info = program.get_file_info(index)?;
+        let name: Box<str> = match &self.strings {
+            Some(strings) => info.name.to_string_lossy(strings)?.into(),
+            None => "<unknown>".into(),
+        };
+        if let Some(index) = self.file_index.get(&name) {
+            return Ok(*index);
+        }
+        let index = self.files.len() as u32;
+        self.files.push(name.clone());
+        self.file_index.insert(name, index);
+        Ok(index)
+    }
+
+    fn row(&mut self, program: &LineProgram, info: &LineInfo, depth: u32) -> Res<Option<Row>> {
+        let (Some(address), Some(len)) = (self.address(info.offset), info.length) else {
+            return Ok(None);
+        };
+        Ok(Some(Row {
+            address,
+            end: address + len as u64,
+            file: self.file(program, info.file_index)?,
+            line: if HIDDEN_LINES.contains(&info.line_start) {
+                0
+            } else {
+                info.line_start
+            },
+            column: info.column_start,
+            depth,
+        }))
+    }
+
+    fn module(&mut self, module: &pdb::ModuleInfo) -> Res<()> {
+        let program = module.line_program()?;
+        let mut lines = program.lines();
+        while let Some(info) = lines.next()? {
+            if let Some(row) = self.row(&program, &info, 0)? {
+                self.rows.push(row);
+            }
+        }
+
+        let mut inlinees = HashMap::new();
+        let mut iter = module.inlinees()?;
+        while let Some(inlinee) = iter.next()? {
+            inlinees.insert(inlinee.index(), inlinee);
+        }
+
+        enum Scope {
+            Function(usize, pdb::PdbInternalSectionOffset),
+            Inline,
+            Other,
+        }
+        let mut scopes: Vec<Scope> = Vec::new();
+
+        let mut symbols = module.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            if symbol.ends_scope() {
+                scopes.pop();
+                continue;
+            }
+            if !symbol.starts_scope() {
+                continue;
+            }
+            match symbol.parse() {
+                Ok(SymbolData::Procedure(proc)) => match self.address(proc.offset) {
+                    Some(address) => {
+                        scopes.push(Scope::Function(self.functions.len(), proc.offset));
+                        self.functions.push(PdbFunction {
+                            address,
+                            size: proc.len as u64,
+                            name: proc.name.to_string().into(),
+                            inlines: Vec::new(),
+                        });
+                    }
+                    None => scopes.push(Scope::Other),
+                },
+                Ok(SymbolData::InlineSite(site)) => {
+                    let depth =
+                        1 + scopes.iter().filter(|s| matches!(s, Scope::Inline)).count() as u32;
+                    let function = scopes.iter().rev().find_map(|s| match s {
+                        Scope::Function(index, offset) => Some((*index, *offset)),
+                        _ => None,
+                    });
+                    scopes.push(Scope::Inline);
+                    let (Some((function, offset)), Some(inlinee)) =
+                        (function, inlinees.get(&site.inlinee))
+                    else {
+                        continue;
+                    };
+
+                    let name: Arc<str> = self
+                        .func_ids
+                        .get(&site.inlinee)
+                        .cloned()
+                        .unwrap_or_else(|| format!("<inlinee {:#x}>", site.inlinee.0).into());
+                    let mut rows = Vec::new();
+                    for info in inline_lines(inlinee.lines(offset, &site).iterator()) {
+                        if let Some(row) = self.row(&program, &info, depth)? {
+                            rows.push(row);
+                        }
+                    }
+                    for row in &rows {
+                        self.functions[function].inlines.push(InlineRange {
+                            low: row.address,
+                            high: row.end,
+                            depth,
+                            name: name.clone(),
+                        });
+                    }
+                    self.rows.extend(rows);
+                }
+                _ => scopes.push(Scope::Other),
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the innermost row for every address, like a DWARF line table would
+    fn flatten(&mut self) -> Vec<PdbLine> {
+        let mut bounds: Vec<u64> = self.rows.iter().flat_map(|r| [r.address, r.end]).collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut best: Vec<Option<usize>> = vec![None; bounds.len()];
+        for (index, row) in self.rows.iter().enumerate() {
+            let mut i = bounds.partition_point(|b| *b < row.address);
+            while i < bounds.len() && bounds[i] < row.end {
+                let deeper = best[i].is_none_or(|old| self.rows[old].depth < row.depth);
+                if deeper {
+                    best[i] = Some(index);
+                }
+                i += 1;
+            }
+        }
+
+        let mut ans: Vec<PdbLine> = Vec::new();
+        for (i, index) in best.iter().enumerate() {
+            let Some(index) = index else {
+                continue;
+            };
+            let row = &self.rows[*index];
+            let (low, high) = (bounds[i], bounds[i + 1]);
+
+            if let Some(last) = ans.last_mut() {
+                let same = (last.file, last.line, last.column) == (row.file, row.line, row.column);
+                if same && last.address + last.size == low {
+                    last.size = high - last.address;
+                    continue;
+                }
+            }
+            ans.push(PdbLine {
+                address: low,
+                size: high - low,
+                file: row.file,
+                line: row.line,
+                column: row.column,
+            });
+        }
+        ans
+    }
+}
+
+/// The line rows of an inline site.
+///
+/// An annotation that can not be read ends the site, the rows before it are kept.
+fn inline_lines(lines: impl Iterator<Item = pdb::Result<LineInfo>>) -> Vec<LineInfo> {
+    lines.map_while(Result::ok).collect()
+}
+
+impl PdbInfo {
+    /// `section_bases` holds the address of every section of the binary in order,
+    /// PDB addresses are (section number, offset) pairs
+    pub fn parse(path: Arc<Path>, data: &[u8], section_bases: &[u64]) -> Res<Self> {
+        let mut pdb = pdb::PDB::open(Cursor::new(data))?;
+        let strings = pdb.string_table().ok();
+        let func_ids = function_ids(&mut pdb)?;
+
+        let dbi = pdb.debug_information()?;
+        let mut builder = Builder {
+            strings,
+            func_ids,
+            section_bases,
+            files: Vec::new(),
+            file_index: HashMap::new(),
+            rows: Vec::new(),
+            functions: Vec::new(),
+        };
+        let mut modules = dbi.modules()?;
+        while let Some(module) = modules.next()? {
+            let Some(info) = pdb.module_info(&module)? else {
+                continue;
+            };
+            builder.module(&info)?;
+        }
+
+        let lines = builder.flatten();
+        let mut functions = builder.functions;
+        functions.sort_by_key(|f| f.address);
+        for function in &mut functions {
+            function.inlines.sort_by_key(|i| (i.low, i.depth));
+        }
+
+        Ok(PdbInfo {
+            path,
+            files: builder.files.into(),
+            lines: lines.into(),
+            functions: functions.into(),
+        })
+    }
+
+    pub fn find_function(&self, address: u64) -> Option<&PdbFunction> {
+        let idx = self
+            .functions
+            .partition_point(|f| f.address <= address)
+            .checked_sub(1)?;
+        let function = &self.functions[idx];
+        (address - function.address < function.size).then_some(function)
+    }
+
+    /// function names at `address`, innermost inlined call first
+    pub fn find_frames(&self, address: u64) -> Vec<&str> {
+        let Some(function) = self.find_function(address) else {
+            return Vec::new();
+        };
+        let mut inlines: Vec<&InlineRange> = function
+            .inlines
+            .iter()
+            .filter(|i| i.low <= address && address < i.high)
+            .collect();
+        inlines.sort_by_key(|i| std::cmp::Reverse(i.depth));
+
+        let mut ans: Vec<&str> = inlines.iter().map(|i| &*i.name).collect();
+        ans.push(&function.name);
+        ans
+    }
+
+    fn location(&self, line: &PdbLine) -> Location<'_> {
+        Location {
+            file: self.files.get(line.file as usize).map(|f| &**f),
+            line: (line.line != 0).then_some(line.line),
+            column: line.column,
+        }
+    }
+
+    pub fn find_location(&self, address: u64) -> Option<Location<'_>> {
+        let idx = self
+            .lines
+            .partition_point(|l| l.address <= address)
+            .checked_sub(1)?;
+        let line = &self.lines[idx];
+        (address - line.address < line.size).then(|| self.location(line))
+    }
+
+    /// line rows overlapping `low..high` as (address, size, location)
+    pub fn find_location_range(&self, low: u64, high: u64) -> Vec<LocationRow<'_>> {
+        let start = self.lines.partition_point(|l| l.address + l.size <= low);
+        self.lines[start..]
+            .iter()
+            .take_while(|l| l.address < high)
+            .map(|l| (l.address, l.size, self.location(l)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //sample_code/pdb/make_pdb.py describes what is in it
+    fn inline_pdb() -> PdbInfo {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_code/pdb/inline.pdb");
+        let data = std::fs::read(&path).unwrap();
+        PdbInfo::parse(path.into(), &data, &[0x1000]).unwrap()
+    }
+
+    fn file_line(pdb: &PdbInfo, address: u64) -> (String, u32) {
+        let loc = pdb.find_location(address).unwrap();
+        (loc.file.unwrap().to_string(), loc.line.unwrap())
+    }
+
+    #[test]
+    fn reads_lines() {
+        let pdb = inline_pdb();
+        assert_eq!(file_line(&pdb, 0x1000), ("main.c".into(), 10));
+        assert_eq!(file_line(&pdb, 0x1020), ("main.c".into(), 13));
+        assert_eq!(file_line(&pdb, 0x102f), ("main.c".into(), 13));
+        assert!(pdb.find_location(0x1030).is_none());
+    }
+
+    #[test]
+    fn reads_inlinees() {
+        let pdb = inline_pdb();
+        assert_eq!(file_line(&pdb, 0x1010), ("helper.h".into(), 3));
+        assert_eq!(file_line(&pdb, 0x1018), ("helper.h".into(), 4));
+        assert_eq!(pdb.find_frames(0x1014), ["helper", "main"]);
+        assert_eq!(pdb.find_frames(0x1008), ["main"]);
+        assert_eq!(pdb.find_function(0x1014).unwrap().size, 0x30);
+    }
+
+    #[test]
+    fn stops_at_unknown_annotations() {
+        let info = |offset| LineInfo {
+            offset: pdb::PdbInternalSectionOffset { offset, section: 1 },
+            length: Some(8),
+            file_index: FileIndex(0),
+            line_start: 3,
+            line_end: 4,
+            column_start: None,
+            column_end: None,
+            kind: pdb::LineInfoKind::Statement,
+        };
+        let lines = vec![
+            Ok(info(0x10)),
+            Err(pdb::Error::UnimplementedFeature("annotation")),
+            Ok(info(0x18)),
+        ];
+        let rows = inline_lines(lines.into_iter());
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].offset.offset, 0x10);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(PdbInfo::parse(Path::new("x.pdb").into(), b"not a pdb", &[]).is_err());
+    }
+}
@@ -1,31 +1,63 @@
+use crate::debug_files::find_pdb_file;
+use crate::debug_files::find_separate_debug_file;
+use crate::debug_files::find_supplementary_file;
+use crate::debug_files::PathRemap;
+use crate::debug_files::DEFAULT_DEBUG_DIRS;
+use crate::debug_map::object_path;
+use crate::debug_map::DebugMap;
+use crate::debug_map::ObjectDebug;
 use crate::errors::StackedError;
 use crate::errors::WrapedError;
+use crate::file_parser::fat_slices;
 use crate::file_parser::map_dissasm;
+use crate::file_parser::normalize_arch;
 use crate::file_parser::CodeRange;
+use crate::file_parser::DebugLocator;
 use crate::file_parser::EStr;
+use crate::file_parser::FatSlice;
 use crate::file_parser::InstructionDetail;
 use crate::file_parser::MachineFile;
+use crate::line_table::SourceEntry;
+use crate::md5::md5;
+use crate::pdb::PdbInfo;
+use crate::symbols::SymbolIndex;
 use addr2line::FrameIter;
+use addr2line::Location;
 use addr2line::LookupContinuation;
 use addr2line::LookupResult;
+use addr2line::SplitDwarfLoad;
 use capstone::Capstone;
+use object::read::archive::ArchiveFile;
+use object::Object;
 use once_cell::unsync::OnceCell;
-use std::collections::{BTreeMap, HashMap};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fmt::Write;
 use std::fs;
 use std::path::Path;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 
 use std::collections::hash_map;
 use typed_arena::Arena;
 
-//probably needed to handle the suplementry matrial
-
 pub struct FileRegistry<'a> {
     pub files_arena: &'a Arena<Vec<u8>>,
     pub map: HashMap<Arc<Path>, Result<MachineFile<'a>, WrapedError>>,
+    /// static libraries (and universal binaries) and the paths their members are registered under in `map`
+    pub archives: HashMap<Arc<Path>, Vec<Arc<Path>>>,
+    /// the universal binaries among `archives`
+    universal: HashSet<Arc<Path>>,
+    /// roots searched for separate debug files (build-id trees and mirrored debuglink paths)
+    pub debug_dirs: Vec<PathBuf>,
+    /// handed to every loaded file so line tables point at local sources
+    pub source_remap: Rc<PathRemap>,
+    /// PDBs named on the command line, tried before the CodeView path of each PE
+    pub pdbs: Vec<PathBuf>,
+    /// slices picked out of universal Mach-O binaries
+    pub arches: Vec<String>,
 }
 
 impl<'a> FileRegistry<'a> {
@@ -33,35 +65,396 @@ impl<'a> FileRegistry<'a> {
         FileRegistry {
             files_arena,
             map: HashMap::new(),
+            archives: HashMap::new(),
+            universal: HashSet::new(),
+            debug_dirs: DEFAULT_DEBUG_DIRS.iter().map(PathBuf::from).collect(),
+            source_remap: Rc::default(),
+            pdbs: Vec::new(),
+            arches: Vec::new(),
+        }
+    }
+
+    fn load_machine(
+        &self,
+        path: &Path,
+        buffer: &'a [u8],
+        base: u64,
+    ) -> Result<MachineFile<'a>, Box<dyn Error>> {
+        let files_arena = self.files_arena;
+        let debug_dirs = &self.debug_dirs;
+        let mut machine = MachineFile::parse_at(buffer, files_arena, base)?;
+        machine.source_remap = self.source_remap.clone();
+
+        //split-DWARF builds may ship their .dwo units packed into <bin>.dwp
+        let dwp_path = dwp_path(path);
+        if dwp_path.is_file() {
+            machine.dwp_path = Some(dwp_path.into());
+        }
+
+        //stripped binaries keep their DWARF in a companion file
+        if !machine.has_dwarf() {
+            if let Some(found) = find_separate_debug_file(&machine.obj, path, debug_dirs) {
+                let data = files_arena.alloc(found.data);
+                machine.set_debug_file(found.path.into(), data)?;
+            }
+        }
+
+        //dwz moves DWARF shared between files into a supplementary file
+        let dwarf_path = match &machine.debug_file {
+            Some(debug) => debug.path.clone(),
+            None => path.into(),
+        };
+        if let Some(found) = find_supplementary_file(machine.dwarf_obj(), &dwarf_path, debug_dirs) {
+            let data = files_arena.alloc(found.data);
+            machine.set_sup_file(found.path.into(), data)?;
+        }
+
+        //MSVC keeps everything in a PDB named by the CodeView debug directory
+        if machine.obj.format() == object::BinaryFormat::Pe {
+            if let Some(found) = find_pdb_file(&machine.obj, path, &self.pdbs, debug_dirs) {
+                let pdb_path: Arc<Path> = found.path.into();
+                //the symbol table still gives function names, a bad PDB should not lose the binary
+                match PdbInfo::parse(pdb_path.clone(), &found.data, &machine.section_addresses()) {
+                    Ok(pdb) => machine.set_pdb(pdb),
+                    Err(e) => {
+                        machine.pdb_error = Some(format!("{}: {}", pdb_path.display(), e).into())
+                    }
+                }
+            }
+        }
+
+        Ok(machine)
+    }
+
+    /// source text some loaded binary carries for `path`, for sources missing on this machine
+    pub fn embedded_source(&self, path: &Path) -> Option<Arc<str>> {
+        self.map
+            .values()
+            .filter_map(|machine| machine.as_ref().ok())
+            .find_map(|machine| machine.get_source_entry(path)?.source)
+    }
+
+    /// true when a loaded binary has an MD5 for `path` and `text` does not match it,
+    /// the file was edited after the build and line numbers may point at the wrong code
+    pub fn is_stale(&self, path: &Path, text: &str) -> bool {
+        let entry = self
+            .map
+            .values()
+            .filter_map(|machine| machine.as_ref().ok())
+            .filter_map(|machine| machine.get_source_entry(path))
+            .find(|entry| entry.md5.is_some());
+        let Some(SourceEntry {
+            md5: Some(checksum),
+            md5_from_gcc,
+            ..
+        }) = entry
+        else {
+            return false;
+        };
+        let mut digest = md5(text.as_bytes());
+        if checksum == digest {
+            return false;
+        }
+        //GNU as 2.40 writes the digest of `.file 0 "dir" "name" md5 0x...` byte reversed into .debug_line
+        //(`md5sum` of the file gives the 0x value gcc emitted, llvm-dwarfdump --debug-line shows it reversed).
+        //clang assembles itself and gets it right, so only GCC units get the reversed check
+        if !md5_from_gcc {
+            return true;
         }
+        digest.reverse();
+        checksum != digest
     }
 
     pub fn get_machine(&mut self, path: Arc<Path>) -> Result<&mut MachineFile<'a>, Box<dyn Error>> {
-        //code looks so ugly because we cant pull into a side function or the borrow checker will freak out
         // println!("geting data for {}",path.to_string_lossy());
 
-        match self.map.entry(path.clone()) {
-            hash_map::Entry::Occupied(entry) => {
-                entry.into_mut().as_mut().map_err(|e| e.clone().into())
+        if !self.is_loaded(&path) {
+            self.load_path(path.clone());
+        }
+        if self.archives.contains_key(&path) {
+            return Err(format!("{} has several members, open one of them", path.display()).into());
+        }
+
+        self.map
+            .get_mut(&path)
+            .unwrap()
+            .as_mut()
+            .map_err(|e| e.clone().into())
+    }
+
+    /// The files `path` stands for: every member of an archive, otherwise just itself
+    pub fn expand(&mut self, path: Arc<Path>) -> Vec<Arc<Path>> {
+        if !self.is_loaded(&path) {
+            self.load_path(path.clone());
+        }
+
+        //--arch would be ignored on anything else, which hides a wrong path or a thin build
+        let loaded =
+            self.archives.contains_key(&path) || self.map.get(&path).is_some_and(|m| m.is_ok());
+        if !self.arches.is_empty() && loaded && !self.universal.contains(&path) {
+            let err = format!(
+                "--arch {} picks a slice of a universal binary, {} is not one",
+                self.arches.join(", "),
+                path.display()
+            );
+            self.archives.remove(&path);
+            self.map
+                .insert(path.clone(), Err(WrapedError::new(err.into())));
+            return vec![path];
+        }
+
+        match self.archives.get(&path) {
+            Some(members) => members.clone(),
+            None => vec![path],
+        }
+    }
+
+    fn is_loaded(&self, path: &Path) -> bool {
+        self.map.contains_key(path) || self.archives.contains_key(path)
+    }
+
+    /// Reads `path` into the registry, there is always an entry for it afterwards
+    fn load_path(&mut self, path: Arc<Path>) {
+        let buffer: &'a [u8] = match fs::read(&*path) {
+            Ok(buffer) => self.files_arena.alloc(buffer),
+            Err(e) => {
+                //`lib.a(foo.o)` names an archive member, not a file
+                if let Some(archive) = split_member_path(&path) {
+                    if !self.is_loaded(&archive) {
+                        self.load_path(archive.into());
+                    }
+                    if self.map.contains_key(&path) {
+                        return;
+                    }
+                }
+                self.map.insert(path, Err(WrapedError::new(e.into())));
+                return;
             }
-            hash_map::Entry::Vacant(entry) => {
-                let buffer = match fs::read(&*path) {
-                    Ok(x) => x,
+        };
+
+        if object::FileKind::parse(buffer).is_ok_and(|kind| kind == object::FileKind::Archive) {
+            match self.load_archive(&path, buffer) {
+                Ok(members) => {
+                    self.archives.insert(path, members);
+                }
+                Err(e) => {
+                    self.map.insert(path, Err(WrapedError::new(e)));
+                }
+            }
+            return;
+        }
+
+        match fat_slices(buffer) {
+            Ok(Some(slices)) => {
+                match self.load_fat(&path, slices) {
+                    Ok(members) => {
+                        self.universal.insert(path.clone());
+                        self.archives.insert(path, members);
+                    }
                     Err(e) => {
-                        return entry
-                            .insert(Err(WrapedError::new(Box::new(e))))
-                            .as_mut()
-                            .map_err(|e| e.clone().into())
+                        self.map.insert(path, Err(WrapedError::new(e)));
                     }
-                };
-                let b = self.files_arena.alloc(buffer);
-                entry
-                    .insert(MachineFile::parse(b).map_err(WrapedError::new))
-                    .as_mut()
-                    .map_err(|e| e.clone().into())
+                }
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                self.map.insert(path, Err(WrapedError::new(e)));
+                return;
             }
         }
+
+        let machine = self.load_machine(&path, buffer, 0);
+        self.map
+            .insert(path.clone(), machine.map_err(WrapedError::new));
+
+        //the debug map objects need the registry so they come after the insert
+        self.load_debug_map(&path);
     }
+
+    /// Registers every object in a static library as `lib.a(member.o)`.
+    ///
+    /// Relocatable members are laid out one after the other so their addresses never overlap.
+    fn load_archive(
+        &mut self,
+        path: &Arc<Path>,
+        data: &'a [u8],
+    ) -> Result<Vec<Arc<Path>>, Box<dyn Error>> {
+        let archive = ArchiveFile::parse(data)?;
+        let mut members = Vec::new();
+        let mut base = 0;
+        for member in archive.members() {
+            let member = member?;
+            let name = String::from_utf8_lossy(member.name());
+            let member_path: Arc<Path> = archive_member_path(path, &name).into();
+            //ar allows repeated names, only the first one is reachable by name
+            if self.map.contains_key(&member_path) {
+                continue;
+            }
+
+            let member_data: &'a [u8] = if member.is_thin() {
+                //thin archives only hold the paths of their members, one missing loses only itself
+                let file = path.parent().unwrap_or(Path::new("")).join(&*name);
+                match fs::read(&file) {
+                    Ok(member_data) => self.files_arena.alloc(member_data),
+                    Err(e) => {
+                        let err = format!("{}: {}", file.display(), e).into();
+                        self.map
+                            .insert(member_path.clone(), Err(WrapedError::new(err)));
+                        members.push(member_path);
+                        continue;
+                    }
+                }
+            } else {
+                member.data(data)?
+            };
+            //symbol tables and other non object members
+            if object::FileKind::parse(member_data).is_err() {
+                continue;
+            }
+
+            let machine = self
+                .load_machine(&member_path, member_data, base)
+                .map(|mut machine| {
+                    base = base.max(machine.layout.end);
+                    machine.archive = Some(path.clone());
+                    machine
+                });
+            self.map
+                .insert(member_path.clone(), machine.map_err(WrapedError::new));
+            members.push(member_path);
+        }
+        Ok(members)
+    }
+
+    /// Registers the slices of a universal binary picked with --arch as `app[x86_64]`
+    fn load_fat(
+        &mut self,
+        path: &Arc<Path>,
+        slices: Vec<FatSlice<'a>>,
+    ) -> Result<Vec<Arc<Path>>, Box<dyn Error>> {
+        let available = slices
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if self.arches.is_empty() {
+            return Err(format!(
+                "{} is a universal binary, pick a slice with --arch ({})",
+                path.display(),
+                available
+            )
+            .into());
+        }
+
+        let mut members = Vec::new();
+        for (name, data) in slices {
+            if !self.arches.iter().any(|arch| normalize_arch(arch) == name) {
+                continue;
+            }
+            let member_path: Arc<Path> = fat_slice_path(path, &name).into();
+            //the real path so a .dSYM next to the binary is found
+            let machine = self.load_machine(path, data, 0).map(|mut machine| {
+                machine.archive = Some(path.clone());
+                machine
+            });
+            self.map
+                .insert(member_path.clone(), machine.map_err(WrapedError::new));
+            self.load_debug_map(&member_path);
+            members.push(member_path);
+        }
+
+        if members.is_empty() {
+            return Err(format!(
+                "{} has no slice for --arch {}, it has {}",
+                path.display(),
+                self.arches.join(", "),
+                available
+            )
+            .into());
+        }
+        Ok(members)
+    }
+
+    /// Loads the .o files an un-dsymutil'd Mach-O binary points at
+    fn load_debug_map(&mut self, path: &Arc<Path>) {
+        let Some(Ok(machine)) = self.map.get(path) else {
+            return;
+        };
+        if machine.has_dwarf() || machine.debug_file.is_some() {
+            return;
+        }
+        let object_map = machine.obj.object_map();
+        if object_map.objects().is_empty() {
+            return;
+        }
+
+        let objects = object_map
+            .objects()
+            .iter()
+            .map(|file| {
+                let obj_path: Arc<Path> = object_path(file, &self.source_remap).into();
+                self.get_machine(obj_path.clone())
+                    .and_then(|obj| ObjectDebug::new(obj_path.clone(), obj))
+                    .map_err(|e| format!("{}: {}", obj_path.display(), e).into())
+            })
+            .map(|res| res.map_err(WrapedError::new))
+            .collect();
+
+        if let Some(Ok(machine)) = self.map.get_mut(path) {
+            machine.set_debug_map(DebugMap::new(object_map, objects));
+        }
+    }
+
+    /// Finds a split unit in the .dwp of whichever loaded file owns `load.parent`
+    pub fn find_packaged_unit<'r>(
+        &self,
+        load: &SplitDwarfLoad<EStr<'r>>,
+    ) -> Option<Arc<gimli::Dwarf<EStr<'r>>>>
+    where
+        'a: 'r,
+    {
+        self.map
+            .values()
+            .filter_map(|res| res.as_ref().ok())
+            .filter(|machine| machine.owns_dwarf(&load.parent))
+            .find_map(|machine| {
+                let package: &gimli::DwarfPackage<EStr<'r>> = machine.get_dwarf_package()?;
+                let unit = package.find_cu(load.dwo_id, &load.parent).ok()??;
+                Some(Arc::new(unit))
+            })
+    }
+}
+
+/// `lib.a` + `foo.o` -> `lib.a(foo.o)`, the way linkers name archive members
+pub fn archive_member_path(archive: &Path, member: &str) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push("(");
+    name.push(member);
+    name.push(")");
+    name.into()
+}
+
+/// `app` + `arm64` -> `app[arm64]`, one slice of a universal binary
+pub fn fat_slice_path(path: &Path, arch: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push("[");
+    name.push(arch);
+    name.push("]");
+    name.into()
+}
+
+/// `lib.a(foo.o)` -> `lib.a`
+fn split_member_path(path: &Path) -> Option<PathBuf> {
+    let (archive, _member) = path.to_str()?.strip_suffix(')')?.rsplit_once('(')?;
+    Some(archive.into())
+}
+
+/// `foo` -> `foo.dwp`, `libfoo.so` -> `libfoo.so.dwp`
+fn dwp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".dwp");
+    name.into()
 }
 
 // pub type AddressFileMapping = HashMap<u64, (String, u32)>; // address -> (file, line)
@@ -121,12 +514,32 @@ fn map_frame_func<'a, E>(
     Ok(())
 }
 
-pub fn resolve_func_name(addr2line: &DebugContext, address: u64) -> Option<String> {
-    // Start the frame lookup process
-    let lookup_result = addr2line.find_frames(address);
+/// Innermost (possibly inlined) function from the PDB, the symbol table is the fallback
+fn pdb_func_name(pdb: &PdbInfo, symbols: &SymbolIndex, address: u64) -> Option<String> {
+    match pdb.find_frames(address).first() {
+        Some(name) => Some(name.to_string()),
+        None => symbols.label(address),
+    }
+}
+
+pub fn resolve_func_name(
+    locator: &DebugLocator,
+    symbols: &SymbolIndex,
+    address: u64,
+) -> Option<String> {
+    if let Some(pdb) = locator.pdb() {
+        return pdb_func_name(pdb, symbols, address);
+    }
 
-    let frames = lookup_result.skip_all_loads().ok()?;
-    select_one_func(frames)
+    // Start the frame lookup process
+    let frames = locator.locate(address).and_then(|(addr2line, probe)| {
+        let lookup_result = addr2line.find_frames(probe);
+        lookup_result.skip_all_loads().ok()
+    });
+
+    frames
+        .and_then(select_one_func)
+        .or_else(|| symbols.label(address))
 }
 
 fn get_func_frames<'a, 'b: 'a, 'c>(
@@ -141,6 +554,12 @@ fn get_func_frames<'a, 'b: 'a, 'c>(
             LookupResult::Load { load, continuation } => {
                 // println!("load case {:?} {:?}",load.parent,load.path);
 
+                // A packaged build has every unit in the .dwp, no need to go looking for .dwo files
+                if let Some(dwo) = registry.find_packaged_unit(&load) {
+                    lookup_result = continuation.resume(Some(dwo));
+                    continue;
+                }
+
                 // Construct the full path for the DWO file if possible
                 let dwo_path = load
                     .comp_dir
@@ -183,54 +602,151 @@ fn get_func_frames<'a, 'b: 'a, 'c>(
     }
 }
 
+/// Innermost function from DWARF, `<symbol+offset>` when DWARF does not cover the address
 pub fn find_func_name<'a, 'b: 'a>(
-    addr2line: &DebugContext<'a>,
+    locator: &DebugLocator<'a>,
     registry: &mut FileRegistry<'b>,
+    symbols: &SymbolIndex,
     address: u64,
 ) -> Option<String> {
-    get_func_frames(addr2line, registry, address).map(select_one_func)?
+    if let Some(pdb) = locator.pdb() {
+        return pdb_func_name(pdb, symbols, address);
+    }
+    locator
+        .locate(address)
+        .and_then(|(addr2line, probe)| get_func_frames(addr2line, registry, probe))
+        .and_then(select_one_func)
+        .or_else(|| symbols.label(address))
 }
 
 pub fn map_funcs<'a, 'b: 'a, E>(
-    addr2line: &DebugContext<'a>,
+    locator: &DebugLocator<'a>,
     registry: &mut FileRegistry<'b>,
+    symbols: &SymbolIndex,
     address: u64,
-    map: impl FnMut(&str) -> Result<(), E>,
+    mut map: impl FnMut(&str) -> Result<(), E>,
 ) -> Result<(), E> {
-    if let Some(frame) = get_func_frames(addr2line, registry, address) {
-        map_frame_func(frame, map)
-    } else {
-        Ok(())
+    let mut found = false;
+    if let Some(pdb) = locator.pdb() {
+        for name in pdb.find_frames(address) {
+            found = true;
+            map(name)?;
+        }
+    } else if let Some(frame) = locator
+        .locate(address)
+        .and_then(|(addr2line, probe)| get_func_frames(addr2line, registry, probe))
+    {
+        map_frame_func(frame, |name| {
+            found = true;
+            map(name)
+        })?;
+    }
+
+    match symbols.find(address) {
+        Some(sym) if !found => map(&sym.name),
+        _ => Ok(()),
+    }
+}
+
+/// One function of the inline chain at an address and the source position in it
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceFrame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl SourceFrame {
+    fn new(function: Option<String>, location: Option<&Location>) -> Self {
+        SourceFrame {
+            function,
+            file: location.and_then(|l| l.file).map(str::to_string),
+            line: location.and_then(|l| l.line),
+            column: location.and_then(|l| l.column),
+        }
     }
 }
 
+/// The inline chain at `address`, innermost first.
+///
+/// Every frame after the first is the function the previous one was inlined into, at the call site.
+/// Without debug info there is a single frame named from the symbol table.
+pub fn find_source_frames<'a, 'b: 'a>(
+    locator: &DebugLocator<'a>,
+    registry: &mut FileRegistry<'b>,
+    symbols: &SymbolIndex,
+    address: u64,
+) -> Vec<SourceFrame> {
+    let mut frames = Vec::new();
+    if let Some(pdb) = locator.pdb() {
+        //the PDB only knows the call sites by line table, so outer frames get no position
+        let location = pdb.find_location(address);
+        for (i, name) in pdb.find_frames(address).into_iter().enumerate() {
+            let location = location.as_ref().filter(|_| i == 0);
+            frames.push(SourceFrame::new(Some(name.to_string()), location));
+        }
+    } else if let Some(mut iter) = locator
+        .locate(address)
+        .and_then(|(addr2line, probe)| get_func_frames(addr2line, registry, probe))
+    {
+        while let Ok(Some(frame)) = iter.next() {
+            let function = frame
+                .function
+                .and_then(|raw| raw.demangle().ok().map(|name| name.to_string()));
+            frames.push(SourceFrame::new(function, frame.location.as_ref()));
+        }
+    }
+
+    if frames.is_empty() {
+        let location = locator.find_location(address).ok().flatten();
+        frames.push(SourceFrame::new(None, location.as_ref()));
+    }
+    if frames.iter().all(|frame| frame.function.is_none()) {
+        frames[0].function = symbols.find(address).map(|sym| sym.name.to_string());
+    }
+    frames
+}
+
 // #[derive(PartialEq,Clone)]
 // pub struct Instruction{
 //     pub detail:InstructionDetail,
 //     pub file: Arc<Path>
 // }
 
+/// An instruction with the loaded file it came from, an archive member or a fat slice.
+///
+/// The slices of a universal binary share addresses so the address alone is not enough.
+pub type MachineInstruction = (Arc<Path>, InstructionDetail);
+
+/// the ranges keep their disassembler since the slices of a universal binary differ in ISA
+#[derive(Default)]
 pub struct LazeyAsm<'a> {
-    ranges: Vec<CodeRange<'a>>,
-    cs: Rc<Capstone>,
-    asm: OnceCell<Box<[InstructionDetail]>>,
+    ranges: Vec<(Rc<Capstone>, Arc<Path>, CodeRange<'a>)>,
+    asm: OnceCell<Box<[MachineInstruction]>>,
 }
 
 impl<'a> LazeyAsm<'a> {
-    pub fn new(cs: Rc<Capstone>) -> Self {
-        Self {
-            ranges: Vec::new(),
-            asm: OnceCell::new(),
-            cs,
-        }
+    /// column of the row holding `address` in `machine`
+    pub fn column_at(&self, machine: &Path, address: u64) -> Option<u32> {
+        self.ranges
+            .iter()
+            .filter(|(_, m, _)| **m == *machine)
+            .map(|(_, _, r)| r)
+            .find(|r| r.address <= address && address - r.address < r.data.len() as u64)
+            .map(|r| r.column)
+            .filter(|c| *c != 0)
     }
 
-    pub fn make_asm(&self) -> Result<&[InstructionDetail], Box<dyn Error>> {
+    pub fn make_asm(&self) -> Result<&[MachineInstruction], Box<dyn Error>> {
         self.asm
             .get_or_try_init(|| {
                 let mut ans = Vec::new();
-                for r in &self.ranges {
-                    map_dissasm(&self.cs, r.data, r.address, &mut |ins| Ok(ans.push(ins)))?;
+                for (cs, machine, r) in &self.ranges {
+                    map_dissasm(cs, r.data, r.address, &mut |ins| {
+                        ans.push((machine.clone(), ins));
+                        Ok(())
+                    })?;
                 }
                 Ok(ans.into())
             })
@@ -241,6 +757,10 @@ impl<'a> LazeyAsm<'a> {
 // #[derive(PartialEq)]
 pub struct CodeFile<'a> {
     pub text: String,
+    /// the text was embedded in the binary, the file itself is not on disk
+    pub from_binary: bool,
+    /// the file on disk does not match the checksum the binary was built with
+    pub stale: bool,
     line_map: OnceCell<HashMap<u32, (usize, usize)>>, //line->byte span
     asm: BTreeMap<u32, HashMap<Arc<Path>, LazeyAsm<'a>>>, //line -> instruction
     pub errors: Vec<(StackedError, Option<Arc<Path>>)>,
@@ -249,12 +769,18 @@ pub struct CodeFile<'a> {
 impl<'a> CodeFile<'a> {
     pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
         let text = fs::read_to_string(path)?;
-        Ok(CodeFile {
+        Ok(CodeFile::from_text(text))
+    }
+
+    fn from_text(text: String) -> Self {
+        CodeFile {
             text,
+            from_binary: false,
+            stale: false,
             line_map: OnceCell::new(),
             asm: BTreeMap::new(),
             errors: Vec::new(),
-        })
+        }
     }
 
     pub fn read_arena<'r>(
@@ -274,10 +800,46 @@ impl<'a> CodeFile<'a> {
         &self,
         line: &u32,
         obj_path: Arc<Path>,
-    ) -> Option<Result<&[InstructionDetail], Box<dyn Error>>> {
+    ) -> Option<Result<&[MachineInstruction], Box<dyn Error>>> {
         self.asm.get(line)?.get(&obj_path).map(|x| x.make_asm()) //.unwrap_or(&[])
     }
 
+    /// column of the line table row holding `address` of `machine` on `line`
+    pub fn get_column(
+        &self,
+        line: u32,
+        obj_path: &Path,
+        machine: &Path,
+        address: u64,
+    ) -> Option<u32> {
+        self.asm
+            .get(&line)?
+            .get(obj_path)?
+            .column_at(machine, address)
+    }
+
+    /// Byte span of the sub-expression that starts at `column` on `line`.
+    ///
+    /// DWARF only records where an expression starts, so it ends at the next column
+    /// the line table has for this line (in any binary).
+    pub fn column_span(&self, line: u32, column: u32) -> Option<(usize, usize)> {
+        let text = self.get_line(line)?;
+        let start = (column as usize)
+            .checked_sub(1)
+            .filter(|s| *s < text.len())?;
+        let end = self
+            .asm
+            .get(&line)
+            .into_iter()
+            .flat_map(|files| files.values())
+            .flat_map(|asm| asm.ranges.iter().map(|(_, _, r)| r.column as usize))
+            .filter(|c| *c > column as usize)
+            .min()
+            .map_or(text.len(), |c| (c - 1).min(text.len()));
+        let end = start + text.get(start..end)?.trim_end().len();
+        (end > start).then_some((start, end))
+    }
+
     #[inline]
     pub fn get_line(&self, line: u32) -> Option<&str> {
         let (start, end) = self.get_line_map().get(&line)?;
@@ -325,15 +887,21 @@ impl<'a> CodeFile<'a> {
             let cs = try_wrapped!(machine_file.get_capstone(), "while making dissasmbler");
             let map = try_wrapped!(machine_file.get_lines_map(), "while making context");
 
+            //archive members also show up under the archive so a walk over it sees all of them
+            let mut keys = vec![obj_path.clone()];
+            keys.extend(machine_file.archive.clone());
+
             if let Some(line_map) = map.get(&path) {
                 for (line, v) in line_map.iter_maped() {
-                    self.asm
-                        .entry(*line)
-                        .or_insert_with(HashMap::new)
-                        .entry(obj_path.clone())
-                        .or_insert_with(|| LazeyAsm::new(cs.clone()))
-                        .ranges
-                        .extend_from_slice(v);
+                    for key in &keys {
+                        self.asm
+                            .entry(*line)
+                            .or_default()
+                            .entry(key.clone())
+                            .or_default()
+                            .ranges
+                            .extend(v.iter().map(|r| (cs.clone(), obj_path.clone(), *r)));
+                    }
                 }
             }
         }
@@ -410,7 +978,14 @@ impl<'data, 'r> CodeRegistry<'data, 'r> {
                 //     .map_err(|e| Box::new(WrapedError::new(e)))
                 // );
 
-                let code_file = match CodeFile::read_arena(&path, self.arena) {
+                //built on another machine, the binary may still carry the text
+                let read = CodeFile::read_arena(&path, self.arena).or_else(|e| {
+                    let text = self.asm.embedded_source(&path).ok_or(e)?;
+                    let code_file = self.arena.alloc(CodeFile::from_text(text.to_string()));
+                    code_file.from_binary = true;
+                    Ok(code_file)
+                });
+                let code_file = match read {
                     Ok(x) => x,
                     Err(e) => {
                         let err = Box::new(WrapedError::new(e));
@@ -419,6 +994,9 @@ impl<'data, 'r> CodeRegistry<'data, 'r> {
                     }
                 };
 
+                if !code_file.from_binary {
+                    code_file.stale = self.asm.is_stale(&path, &code_file.text);
+                }
                 code_file.populate(self.asm, path);
                 if dwarf_errors {
                     code_file.get_error()?
@@ -440,6 +1018,44 @@ impl<'data, 'r> CodeRegistry<'data, 'r> {
     pub fn get_existing_machine(&self, path: &Path) -> Option<&MachineFile<'data>> {
         self.asm.map.get(path).map(|x| x.as_ref().ok())?
     }
+
+    /// The loaded file holding `address`, for an archive the member it falls in.
+    ///
+    /// The slices of a universal binary overlap, this is the first one holding it.
+    pub fn machine_path_at(&self, path: &Path, address: u64) -> Option<Arc<Path>> {
+        match self.asm.archives.get(path) {
+            Some(members) => members
+                .iter()
+                .find(|member| {
+                    self.get_existing_machine(member)
+                        .is_some_and(|machine| machine.has_code_at(address))
+                })
+                .cloned(),
+            None => Some(self.asm.map.get_key_value(path)?.0.clone()),
+        }
+    }
+
+    /// the loaded file at `path`, or every member when it is an archive
+    pub fn get_machines(&self, path: &Path) -> Vec<&MachineFile<'data>> {
+        self.machine_paths(path)
+            .iter()
+            .filter_map(|member| self.get_existing_machine(member))
+            .collect()
+    }
+
+    /// the paths `get_machines` looks under
+    pub fn machine_paths(&self, path: &Path) -> Vec<Arc<Path>> {
+        match self.asm.archives.get(path) {
+            Some(members) => members.clone(),
+            None => self
+                .asm
+                .map
+                .get_key_value(path)
+                .map(|(k, _)| k.clone())
+                .into_iter()
+                .collect(),
+        }
+    }
 }
 
 // pub fn format_inst_debug<'a, 'b: 'a, 'c>(
@@ -502,3 +1118,47 @@ impl<'data, 'r> CodeRegistry<'data, 'r> {
 //     //     resolve_func_name(self.addr2line,address)
 //     // }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thin_archive_keeps_members_it_can_read() {
+        //sample_code/archive/build.sh leaves g.o out on purpose
+        let lib: Arc<Path> = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("sample_code/archive/libthin.a")
+            .into();
+        let arena = Arena::new();
+        let mut registry = FileRegistry::new(&arena);
+        let members = registry.expand(lib.clone());
+        assert_eq!(members.len(), 2);
+
+        let f = registry.get_machine(archive_member_path(&lib, "f.o").into());
+        assert!(f.unwrap().has_dwarf());
+        let g = registry.get_machine(archive_member_path(&lib, "g.o").into());
+        let err = g.err().unwrap().to_string();
+        assert!(err.contains("g.o"), "{}", err);
+    }
+
+    #[test]
+    fn relative_remap_matches_canonical_paths() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_code");
+        let arena = Arena::new();
+        let mut registry = FileRegistry::new(&arena);
+        //tests run from the manifest dir, so this is a relative TO
+        let built = "/home/user/Desktop/rust_stuff/SourceViewer/sample_code";
+        registry.source_remap = PathRemap::new(vec![(built.into(), "sample_code".into())]).into();
+
+        let machine = registry
+            .get_machine(root.join("hello-world").into())
+            .unwrap();
+        let map = machine.get_lines_map().unwrap();
+        //walk opens the canonical path, so that is the key it has to find
+        let source: Arc<Path> = fs::canonicalize(root.join("hello-world.c")).unwrap().into();
+        assert!(map.get(&source).is_some());
+
+        let root = fs::canonicalize(&root).unwrap();
+        assert_eq!(machine.source_remap.remapped_root(&source), Some(&*root));
+    }
+}
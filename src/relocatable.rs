@@ -0,0 +1,331 @@
+use object::{
+    Architecture, BinaryFormat, Object, ObjectKind, ObjectSection, ObjectSymbol, RelocationFlags,
+    RelocationKind, RelocationTarget, SectionIndex, SectionKind,
+};
+use std::collections::HashMap;
+use typed_arena::Arena;
+
+/// Where the sections of a relocatable object were placed.
+///
+/// ELF and COFF objects start every section at address 0, so for lookups the sections get
+/// packed one after the other (the first one stays at 0) and DWARF is relocated to match.
+/// That placement never reaches the user: `shown_address` turns it back into the offset in
+/// the section, which is what `objdump -d` prints, and `placed_addresses` goes the other way.
+/// Mach-O objects already give their sections distinct addresses, so they are only moved
+/// as a whole, which keeps archive members from overlapping. Linked files get an empty layout.
+#[derive(Debug, Default, Clone)]
+pub struct SectionLayout {
+    bases: HashMap<SectionIndex, u64>,
+    /// placed start, placed end and object address of every placed section
+    spans: Vec<(u64, u64, u64)>,
+    /// how far a Mach-O object was moved, its symbols and DWARF hold object addresses
+    slide: Option<u64>,
+    /// first free address after the placed sections
+    pub end: u64,
+}
+
+impl SectionLayout {
+    pub fn new(obj: &object::File) -> Self {
+        Self::new_at(obj, 0)
+    }
+
+    /// Places the sections starting at `start`, archive members go one after the other
+    pub fn new_at(obj: &object::File, start: u64) -> Self {
+        if obj.kind() != ObjectKind::Relocatable {
+            return SectionLayout::default();
+        }
+        if obj.format() == BinaryFormat::MachO {
+            return Self::slide_macho(obj, start);
+        }
+
+        let mut bases = HashMap::new();
+        let mut spans = Vec::new();
+        let mut next = start;
+        for section in obj.sections() {
+            if !is_placed(section.kind()) {
+                continue;
+            }
+            let align = section.align().max(1);
+            let base = next.div_ceil(align) * align;
+            bases.insert(section.index(), base);
+            next = base + section.size();
+            spans.push((base, next, section.address()));
+        }
+        SectionLayout {
+            bases,
+            spans,
+            slide: None,
+            end: next,
+        }
+    }
+
+    fn slide_macho(obj: &object::File, start: u64) -> Self {
+        let mut bases = HashMap::new();
+        let mut spans = Vec::new();
+        let mut end = start;
+        for section in obj.sections() {
+            if !is_placed(section.kind()) {
+                continue;
+            }
+            let base = start + section.address();
+            bases.insert(section.index(), base);
+            end = end.max(base + section.size());
+            spans.push((base, base + section.size(), section.address()));
+        }
+        SectionLayout {
+            bases,
+            spans,
+            slide: Some(start),
+            end,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bases.is_empty()
+    }
+
+    /// the address the object itself gives `address`, the offset in its section for ELF and COFF
+    pub fn shown_address(&self, address: u64) -> u64 {
+        self.spans
+            .iter()
+            .find(|(start, end, _)| *start <= address && address < *end)
+            .map_or(address, |(start, _, object)| address - start + object)
+    }
+
+    /// every placed address shown as `shown`, one per section that is long enough
+    pub fn placed_addresses(&self, shown: u64) -> Vec<u64> {
+        if self.is_empty() {
+            return vec![shown];
+        }
+        self.spans
+            .iter()
+            .filter(|(start, end, object)| *object <= shown && shown - object < end - start)
+            .map(|(start, _, object)| start + (shown - object))
+            .collect()
+    }
+
+    pub fn section_address(&self, section: &object::Section) -> u64 {
+        self.bases
+            .get(&section.index())
+            .copied()
+            .unwrap_or(section.address())
+    }
+
+    /// symbol values in an object are offsets into their section
+    pub fn symbol_address(&self, symbol: &object::Symbol) -> u64 {
+        if let Some(slide) = self.slide {
+            return match symbol.section_index() {
+                Some(_) => slide + symbol.address(),
+                None => symbol.address(),
+            };
+        }
+        let base = symbol
+            .section_index()
+            .and_then(|index| self.bases.get(&index))
+            .copied()
+            .unwrap_or(0);
+        base + symbol.address()
+    }
+
+    fn target_address(&self, obj: &object::File, target: RelocationTarget) -> Option<u64> {
+        match target {
+            RelocationTarget::Symbol(index) => {
+                Some(self.symbol_address(&obj.symbol_by_index(index).ok()?))
+            }
+            //Mach-O section relocations already hold the object address of their target
+            RelocationTarget::Section(_) if self.slide.is_some() => self.slide,
+            RelocationTarget::Section(index) => Some(self.bases.get(&index).copied().unwrap_or(0)),
+            _ => None,
+        }
+    }
+
+    /// offset of the target inside its own section (SECREL and friends)
+    fn target_offset(&self, obj: &object::File, target: RelocationTarget) -> Option<u64> {
+        match target {
+            RelocationTarget::Symbol(index) => Some(obj.symbol_by_index(index).ok()?.address()),
+            RelocationTarget::Section(_) => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Applies the relocations of the section `name` to its (already decompressed) `data`.
+    ///
+    /// Only what DWARF and unwind tables use is handled: absolute addresses, section offsets
+    /// and, in placed sections like `.eh_frame`, pc relative ones.
+    pub fn relocate<'a>(
+        &self,
+        obj: &object::File,
+        name: &str,
+        data: &'a [u8],
+        files_arena: &'a Arena<Vec<u8>>,
+    ) -> &'a [u8] {
+        if self.is_empty() {
+            return data;
+        }
+        let Some(section) = obj.section_by_name(name) else {
+            return data;
+        };
+
+        let mut relocations = section.relocations().peekable();
+        if relocations.peek().is_none() {
+            return data;
+        }
+
+        //where the field of a pc relative relocation ends up, Mach-O ones move with their target
+        let place = match self.slide {
+            Some(_) => None,
+            None => self.bases.get(&section.index()).copied(),
+        };
+        let little = obj.is_little_endian();
+        let subtractor = macho_subtractor(obj.architecture());
+        let mut out = data.to_vec();
+        let mut in_pair = false;
+        for (offset, reloc) in relocations {
+            //`a - b` pairs do not change when the whole object moves, leave both halves alone
+            if let RelocationFlags::MachO { r_type, .. } = reloc.flags() {
+                if Some(r_type) == subtractor {
+                    in_pair = true;
+                    continue;
+                }
+            }
+            if std::mem::take(&mut in_pair) {
+                continue;
+            }
+            let value = match reloc.kind() {
+                RelocationKind::Absolute | RelocationKind::ImageOffset => {
+                    self.target_address(obj, reloc.target())
+                }
+                RelocationKind::SectionOffset => self.target_offset(obj, reloc.target()),
+                RelocationKind::Relative => place.and_then(|place| {
+                    let target = self.target_address(obj, reloc.target())?;
+                    Some(target.wrapping_sub(place + offset))
+                }),
+                _ => None,
+            };
+            let Some(value) = value else {
+                continue;
+            };
+
+            let size = reloc.size() as usize / 8;
+            let Some(field) = usize::try_from(offset)
+                .ok()
+                .and_then(|start| out.get_mut(start..start.checked_add(size)?))
+            else {
+                continue;
+            };
+
+            let addend = if reloc.has_implicit_addend() {
+                let addend = read_field(field, little);
+                match reloc.kind() {
+                    //a pc relative addend is usually negative
+                    RelocationKind::Relative => sign_extend(addend, size),
+                    _ => addend as i64,
+                }
+            } else {
+                reloc.addend()
+            };
+            write_field(field, value.wrapping_add_signed(addend), little);
+        }
+
+        files_arena.alloc(out)
+    }
+}
+
+fn is_placed(kind: SectionKind) -> bool {
+    matches!(
+        kind,
+        //x86-64 `.eh_frame` has its own section type
+        SectionKind::Elf(object::elf::SHT_X86_64_UNWIND)
+            | SectionKind::Text
+            | SectionKind::Data
+            | SectionKind::ReadOnlyData
+            | SectionKind::ReadOnlyDataWithRel
+            | SectionKind::ReadOnlyString
+            | SectionKind::UninitializedData
+            | SectionKind::Tls
+            | SectionKind::UninitializedTls
+    )
+}
+
+fn macho_subtractor(arch: Architecture) -> Option<u8> {
+    match arch {
+        Architecture::X86_64 => Some(object::macho::X86_64_RELOC_SUBTRACTOR),
+        Architecture::Aarch64 => Some(object::macho::ARM64_RELOC_SUBTRACTOR),
+        _ => None,
+    }
+}
+
+fn read_field(field: &[u8], little: bool) -> u64 {
+    let mut bytes = [0u8; 8];
+    if little {
+        bytes[..field.len()].copy_from_slice(field);
+        u64::from_le_bytes(bytes)
+    } else {
+        bytes[8 - field.len()..].copy_from_slice(field);
+        u64::from_be_bytes(bytes)
+    }
+}
+
+fn sign_extend(value: u64, size: usize) -> i64 {
+    match size {
+        1..=7 => {
+            let unused = 64 - 8 * size as u32;
+            ((value << unused) as i64) >> unused
+        }
+        _ => value as i64,
+    }
+}
+
+fn write_field(field: &mut [u8], value: u64, little: bool) {
+    let len = field.len();
+    if little {
+        field.copy_from_slice(&value.to_le_bytes()[..len]);
+    } else {
+        field.copy_from_slice(&value.to_be_bytes()[8 - len..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_section_offsets() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/sample_code/weird_windows_overlaping_address.o"
+        );
+        let data = std::fs::read(path).unwrap();
+        let obj = object::File::parse(&*data).unwrap();
+        let layout = SectionLayout::new(&obj);
+
+        let text = obj.section_by_name(".text").unwrap();
+        let startup = obj.section_by_name(".text.startup").unwrap();
+        let startup_base = layout.section_address(&startup);
+        //both start at 0 in the object, only one can be there once placed
+        assert_eq!(layout.section_address(&text), 0);
+        assert!(startup_base >= text.size());
+
+        assert_eq!(layout.shown_address(0x10), 0x10);
+        assert_eq!(layout.shown_address(startup_base + 0x10), 0x10);
+        let placed = layout.placed_addresses(0x10);
+        assert!(placed.contains(&0x10) && placed.contains(&(startup_base + 0x10)));
+
+        //past the end of .text only the longer section holds it
+        let placed = layout.placed_addresses(0x100);
+        assert!(!placed.contains(&0x100));
+        assert!(placed.contains(&(startup_base + 0x100)));
+        assert!(placed.iter().all(|a| layout.shown_address(*a) == 0x100));
+    }
+
+    #[test]
+    fn linked_files_are_not_moved() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/sample_code/hello-world");
+        let data = std::fs::read(path).unwrap();
+        let obj = object::File::parse(&*data).unwrap();
+        let layout = SectionLayout::new(&obj);
+        assert!(layout.is_empty());
+        assert_eq!(layout.shown_address(0x1149), 0x1149);
+        assert_eq!(layout.placed_addresses(0x1149), [0x1149]);
+    }
+}
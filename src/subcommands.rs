@@ -1,15 +1,29 @@
+use crate::args::parse_address;
 use crate::args::FileSelection;
+use crate::args::LoadOpts;
+use crate::config::get_debug_config_path;
 use crate::config::get_walk_config_path;
+use crate::config::DebugConfig;
+use crate::core_dump::CoreDump;
+use crate::debug_files::PathRemap;
+use crate::dwarf_dump::dump_dwarf;
+use crate::dwarf_dump::DumpFilter;
+use crate::file_parser::DebugLocator;
 use crate::file_parser::InstructionDetail;
+use crate::log_scan::frame_number;
+use crate::log_scan::scan_line;
+use crate::log_scan::LogAddress;
 use crate::program_context::find_func_name;
+use crate::program_context::find_source_frames;
 use crate::program_context::map_funcs;
 use crate::program_context::CodeRegistry;
 use crate::walk;
 use crate::walk::FileResult;
 use crate::walk::GlobalState;
 use crate::walk::TerminalSession;
-use fallible_iterator::FallibleIterator;
+use std::fmt::Write;
 use std::path::Path;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -18,8 +32,13 @@ use crate::file_parser::MachineFile;
 use crate::file_parser::Section;
 use crate::program_context::resolve_func_name;
 use crate::program_context::FileRegistry;
+use crate::program_context::SourceFrame;
+use crate::symbols::SymbolIndex;
+use crate::types::{find_type_layouts, is_comment};
+use crate::variables::format_locations;
 // use crate::program_context::format_inst_debug;
 use colored::*;
+use serde::Serialize;
 use std::collections::HashSet;
 use std::error::Error;
 use std::fs;
@@ -30,28 +49,244 @@ use crate::println;
 
 // use crate::program_context::AddressFileMapping;
 
+fn make_registry<'a>(
+    arena: &'a Arena<Vec<u8>>,
+    load: &LoadOpts,
+) -> Result<FileRegistry<'a>, Box<dyn Error>> {
+    let config = DebugConfig::get_global()?;
+    let mut registry = FileRegistry::new(arena);
+
+    //command line first, then the config file, then the system defaults
+    let mut debug_dirs = load.debug_dirs.clone();
+    debug_dirs.extend_from_slice(config.get_debug_search_dirs());
+    debug_dirs.append(&mut registry.debug_dirs);
+    registry.debug_dirs = debug_dirs;
+
+    let mut rules = load.remap.clone();
+    rules.extend(config.get_source_remap()?);
+    registry.source_remap = PathRemap::new(rules).into();
+    registry.pdbs = load.pdbs.clone();
+    registry.arches = load.arches.clone();
+
+    Ok(registry)
+}
+
+/// the command line paths with every archive replaced by its members
+fn expand_paths(registry: &mut FileRegistry, file_paths: Vec<PathBuf>) -> Vec<Arc<Path>> {
+    file_paths
+        .into_iter()
+        .flat_map(|path| registry.expand(path.into()))
+        .collect()
+}
+
+fn print_debug_file(machine_file: &MachineFile) -> Result<(), Box<dyn Error>> {
+    if let Some(debug) = &machine_file.debug_file {
+        println!(
+            "{}",
+            format!("using debug info from {:?}", debug.path).green()
+        );
+    }
+    if let Some(sup) = &machine_file.sup_file {
+        println!(
+            "{}",
+            format!("using supplementary debug info from {:?}", sup.path).green()
+        );
+    }
+    if let Some(pdb) = &machine_file.pdb {
+        println!("{}", format!("using PDB from {:?}", pdb.path).green());
+    }
+    if let Some(err) = &machine_file.pdb_error {
+        println!("{}", format!("ignoring unreadable PDB {}", err).red());
+    }
+    if let Some(debug_map) = &machine_file.debug_map {
+        println!(
+            "{}",
+            format!(
+                "using debug map with {} object files",
+                debug_map.objects.len()
+            )
+            .green()
+        );
+        for err in debug_map.objects.iter().filter_map(|o| o.as_ref().err()) {
+            println!("{}", format!("  missing debug map object: {}", err).red());
+        }
+    }
+    Ok(())
+}
+
+/// Gets `obj_file` ready for walking, an archive brings all of its members
+/// What walk loads up front for the binary
+struct WalkFiles {
+    symbols: Arc<SymbolIndex>,
+    /// where a `--remap` rule moved the sources, walk starts there
+    root: Option<Arc<Path>>,
+}
+
+fn load_walk_files(
+    code_files: &mut CodeRegistry,
+    obj_file: Arc<Path>,
+) -> Result<WalkFiles, Box<dyn Error>> {
+    let mut symbols = Vec::new();
+    let mut root = None;
+    for path in code_files.asm.expand(obj_file) {
+        let machine_file = code_files.visit_machine_file(path)?;
+        let map = machine_file.get_lines_map()?;
+        machine_file.get_capstone()?;
+        symbols.push(machine_file.get_symbols());
+
+        if root.is_none() {
+            let remap = &machine_file.source_remap;
+            root = map
+                .files()
+                .find_map(|file| remap.remapped_root(file))
+                .filter(|dir| dir.is_dir())
+                .map(Arc::from);
+        }
+    }
+
+    let symbols = match symbols.len() {
+        1 => symbols.pop().unwrap(),
+        _ => SymbolIndex::merged(&symbols).into(),
+    };
+    Ok(WalkFiles { symbols, root })
+}
+
+/// What a core dump says about the binary being walked
+struct Crash {
+    /// link time addresses of the frames in the binary, innermost first
+    frames: Vec<u64>,
+    /// the first frame is where the fault happened rather than a return address
+    faulted: bool,
+    /// where the binary was mapped in the crashed process
+    bias: u64,
+    file: Option<PathBuf>,
+    line: Option<usize>,
+}
+
+/// Unwinds the crashed thread of `core_path` and prints its backtrace
+fn load_crash(
+    core_path: &Path,
+    registry: &mut FileRegistry,
+    obj_file: &Arc<Path>,
+) -> Result<Crash, Box<dyn Error>> {
+    let data = fs::read(core_path)?;
+    let core = CoreDump::parse(&data).map_err(|e| format!("{}: {}", core_path.display(), e))?;
+    let stack = core.backtrace(registry);
+
+    let machine_file = registry.get_machine(obj_file.clone())?;
+    let bias = core.load_bias(obj_file, &machine_file.obj).ok_or_else(|| {
+        format!(
+            "{} is not mapped in {}",
+            obj_file.display(),
+            core_path.display()
+        )
+    })?;
+    let ours: Vec<Option<u64>> = stack
+        .iter()
+        .map(|pc| Some(pc.wrapping_sub(bias)).filter(|a| machine_file.has_code_at(*a)))
+        .collect();
+    let locator = machine_file.get_locator()?;
+    let symbols = machine_file.get_symbols();
+
+    println!(
+        "{}",
+        format!("backtrace from {:?}", core_path).green().bold()
+    );
+    for (i, (pc, address)) in stack.iter().zip(&ours).enumerate() {
+        let module = match core.mapping_at(*pc) {
+            Some(mapping) => format!(
+                "{}+{:#x}",
+                mapping.path.display(),
+                pc - mapping.start + mapping.offset
+            ),
+            None => "<unmapped>".to_string(),
+        };
+        //a return address can be past the end of a function that calls a noreturn one
+        let probe = address.map(|a| if i == 0 { a } else { a - 1 });
+        let func = probe
+            .and_then(|a| find_func_name(&locator, registry, &symbols, a))
+            .unwrap_or_default();
+        println!("#{:<3} {:#018x} {} {}", i, pc, func.cyan(), module);
+    }
+
+    let faulted = ours.first().is_some_and(Option::is_some);
+    //callers without line info (like _start) have nothing to show
+    let frames: Vec<u64> = ours
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, address)| {
+            let address = address?;
+            let has_line = locator
+                .find_location(address - 1)
+                .is_ok_and(|loc| loc.is_some_and(|loc| loc.line.is_some()));
+            (i == 0 || has_line).then_some(address)
+        })
+        .collect();
+    let innermost = frames
+        .first()
+        .map(|a| if faulted { *a } else { a - 1 })
+        .ok_or_else(|| format!("no frame of the crash is in {}", obj_file.display()))?;
+
+    let machine_file = registry.get_machine(obj_file.clone())?;
+    let (file, line) = match locator.find_location(innermost)? {
+        Some(loc) => (
+            loc.file.map(|f| machine_file.source_path(f).into_owned()),
+            loc.line.map(|l| l as usize),
+        ),
+        None => (None, None),
+    };
+    Ok(Crash {
+        frames,
+        faulted,
+        bias,
+        file,
+        line,
+    })
+}
+
 pub fn walk_command(
     obj_file: Arc<Path>,
     file: Option<PathBuf>,
     line: Option<usize>,
+    core: Option<PathBuf>,
+    load: &LoadOpts,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let asm_arena = Arena::new();
     let code_arena = Arena::new();
-    let mut registry = FileRegistry::new(&asm_arena);
+    let mut registry = make_registry(&asm_arena, load)?;
+
+    //a core dump opens the walk where the process crashed
+    let crash = match core {
+        Some(core) => Some(load_crash(&core, &mut registry, &obj_file)?),
+        None => None,
+    };
+    let (file, line) = match &crash {
+        Some(crash) if file.is_none() => (crash.file.clone(), crash.line),
+        _ => (file, line),
+    };
+
     let mut code_files = CodeRegistry::new(&mut registry, &code_arena);
 
     println!("visiting file {:?}", &*obj_file);
-    let machine_file = code_files.visit_machine_file(obj_file.clone())?;
-    machine_file.get_lines_map()?;
-    machine_file.get_capstone()?;
+    let WalkFiles { symbols, root } = load_walk_files(&mut code_files, obj_file.clone())?;
 
     // let mut terminal = create_terminal()?;
     // let _cleanup = TerminalCleanup;
-    let mut state = GlobalState::start()?;
+    let mut state = match root {
+        Some(dir) => GlobalState::start_from(dir)?,
+        None => GlobalState::start()?,
+    };
+    state.symbols = symbols;
+    //a crash is shown at the addresses it happened at unless told otherwise
+    state.bias = match &crash {
+        Some(crash) if load.base == 0 => crash.bias,
+        _ => load.base,
+    };
     let mut session = TerminalSession::new(&mut state)?;
 
     if let Some(path) = file {
-        let path: Arc<Path> = fs::canonicalize(path)?.into();
+        //a source that is not on disk can still be embedded in the binary
+        let path: Arc<Path> = fs::canonicalize(&path).unwrap_or(path).into();
         let code_file = code_files
             .get_source_file(path.clone(), true)
             .map_err(|e| format!("Failed to load source {:?}: {}", path, e))?;
@@ -61,6 +296,9 @@ pub fn walk_command(
             file_state.file_scroll = line.saturating_sub(1);
             file_state.cursor = line.saturating_sub(1);
         }
+        if let Some(crash) = &crash {
+            file_state.select_frames(&crash.frames, crash.faulted, &obj_file, &mut code_files)?;
+        }
 
         let mut last_frame = Instant::now();
         match TerminalSession::walk_file_loop(
@@ -80,22 +318,36 @@ pub fn walk_command(
     session.walk_directory_loop(&mut code_files, obj_file)
 }
 
-pub fn lines_command(file_paths: Vec<PathBuf>, ignore_unknown: bool) -> Result<(), Box<dyn Error>> {
+pub fn lines_command(
+    file_paths: Vec<PathBuf>,
+    ignore_unknown: bool,
+    inline_chain: bool,
+    vars: bool,
+    cfa: bool,
+    load: &LoadOpts,
+) -> Result<(), Box<dyn Error>> {
     let arena = Arena::new();
-    let mut registry = FileRegistry::new(&arena);
+    let mut registry = make_registry(&arena, load)?;
     // Iterate over each file path and process it
-    for file_path in file_paths {
+    for file_path in expand_paths(&mut registry, file_paths) {
         println!("{}", format!("Loading file {:?}", file_path).green().bold());
-        let machine_file = registry.get_machine(file_path.into())?;
-        let ctx = machine_file.get_addr2line()?;
+        let machine_file = registry.get_machine(file_path.clone())?;
+        print_debug_file(machine_file)?;
+        let locator = machine_file.get_locator()?;
+        let symbols = machine_file.get_symbols();
+        let remap = machine_file.source_remap.clone();
+        let variables = vars.then(|| machine_file.get_variables()).transpose()?;
+        let frames = cfa.then(|| machine_file.get_frames()).flatten();
+        let line_flags = machine_file.get_line_flags()?;
         let cs = create_capstone(machine_file.obj.architecture())?;
+        let layout = machine_file.layout.clone();
 
         for section in &machine_file.sections.clone() {
             if let Section::Code(code_section) = section {
                 println!("{}", section.name());
 
                 code_section.map_asm(&cs, &mut |ins| {
-                    let (file, line) = match ctx.find_location(ins.address)? {
+                    let (file, line) = match locator.find_location(ins.address)? {
                         Some(loc) => {
                             if ignore_unknown && (loc.file.is_none() || loc.line.is_none()) {
                                 return Ok(()); //closure
@@ -114,23 +366,62 @@ pub fn lines_command(file_paths: Vec<PathBuf>, ignore_unknown: bool) -> Result<(
                             ("<unknown>".to_string(), "<unknown>".to_string())
                         }
                     };
+                    let shown = layout.shown_address(ins.address).wrapping_add(load.base);
                     let asm = format!(
                         "{:#010x}: {:<6} {:<15}",
-                        ins.address,
+                        shown,
                         ins.mnemonic,
-                        ins.op_str, //this needs a fixup
+                        code_section.shown_op_str(&cs, ins, shown)?,
                     );
 
-                    let func = find_func_name(&ctx, &mut registry, ins.address)
+                    let func = find_func_name(&locator, &mut registry, &symbols, ins.address)
                         .unwrap_or("<unknown>".to_string());
 
+                    let vars = match &variables {
+                        Some(variables) => {
+                            match format_locations(&variables.variables_at(ins.address)) {
+                                v if v.is_empty() => v,
+                                v => format!(" {}", v),
+                            }
+                        }
+                        None => String::new(),
+                    };
+
+                    let cfa = frames
+                        .as_ref()
+                        .and_then(|frames| frames.describe(ins.address))
+                        .map(|cfa| format!(" {}", cfa))
+                        .unwrap_or_default();
+
+                    //rows in the middle of a statement are dimmed, breakpoints would not land there
+                    let flags = line_flags.flags_at(ins.address).unwrap_or_default();
+                    let asm = match flags.is_stmt {
+                        true => asm.bold(),
+                        false => asm.dimmed(),
+                    };
+                    let markers = match flags.to_string() {
+                        m if m.is_empty() => m,
+                        m => format!(" {}", m),
+                    };
+
                     println!(
-                        "{} {} {}:{}",
-                        asm.bold(),
+                        "{} {} {}:{}{}{}{}",
+                        asm,
                         func.cyan(),
                         file.to_string().yellow(),
-                        line.to_string().blue()
+                        line.to_string().blue(),
+                        markers.blue(),
+                        cfa.green(),
+                        vars.magenta()
                     );
+                    if inline_chain {
+                        //the first frame is the line above, the rest are its callers
+                        let frames =
+                            source_frames(&locator, &mut registry, &symbols, &remap, ins.address);
+                        for frame in frames.iter().skip(1) {
+                            println!("    inlined into {}", format_frame(frame));
+                        }
+                    }
                     Ok(())
                 })?;
             }
@@ -140,66 +431,65 @@ pub fn lines_command(file_paths: Vec<PathBuf>, ignore_unknown: bool) -> Result<(
     Ok(())
 }
 
-use object::{File, Object, ObjectSection};
-fn list_dwarf_sections<'a>(obj_file: &'a File<'a>) -> Result<(), Box<dyn std::error::Error>> {
-    let sections = [
-        ".debug_abbrev",
-        ".debug_addr",
-        ".debug_aranges",
-        ".debug_info",
-        ".debug_line",
-        ".debug_line_str",
-        ".debug_str",
-        ".debug_str_offsets",
-        ".debug_types",
-        ".debug_loc",
-        ".debug_ranges",
-    ];
-
-    for section_name in &sections {
-        // Find the section by name, get the data if available, or return an empty slice
-        let section_data = obj_file
-            .section_by_name(section_name)
-            .and_then(|x| x.data().ok())
-            .unwrap_or(&[]);
-
-        // Print the section name and content as UTF-8 (if possible)
-        println!(
-            "{}:\n{}",
-            section_name.blue(),
-            String::from_utf8_lossy(section_data)
-        );
-    }
-    Ok(())
-}
+use object::{Object, ObjectSegment};
 
-pub fn dwarf_dump_command(file_paths: Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
-    let message = "NOTE: this comand is not finised".to_string().red();
-    println!("{}", message);
+pub fn dwarf_dump_command(
+    file_paths: Vec<PathBuf>,
+    filter: DumpFilter,
+    load: &LoadOpts,
+) -> Result<(), Box<dyn Error>> {
+    let arena = Arena::new();
+    let mut registry = make_registry(&arena, load)?;
     // Iterate over each file path and process it
-    for file_path in file_paths {
+    for file_path in expand_paths(&mut registry, file_paths) {
         println!("{}", format!("Loading file {:?}", file_path).green().bold());
-        let buffer = fs::read(file_path)?;
-        let machine_file = MachineFile::parse(&buffer)?;
-        // let dwarf = machine_file.load_dwarf()?;
-        // println!("{:#?}",dwarf );
-        list_dwarf_sections(&machine_file.obj)?;
+        let machine_file = registry.get_machine(file_path.clone())?;
+        print_debug_file(machine_file)?;
+        let dwarf = machine_file.load_dwarf()?;
+        dump_dwarf(&dwarf, machine_file.obj.architecture(), &filter)?;
     }
-    println!("{}", message);
 
     Ok(())
 }
 
-pub fn sections_command(file_paths: Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+/// pahole for one type: every layout found under `name`, comments (holes, padding) in yellow
+pub fn types_command(file_path: &Path, name: &str, load: &LoadOpts) -> Result<(), Box<dyn Error>> {
+    let arena = Arena::new();
+    let mut registry = make_registry(&arena, load)?;
+    let machine_file = registry.get_machine(file_path.into())?;
+    let dwarf = machine_file.load_dwarf()?;
+
+    let layouts = find_type_layouts(&dwarf, name)?;
+    if layouts.is_empty() {
+        return Err(format!("no struct, class, union or enum named {:?}", name).into());
+    }
+    for (i, layout) in layouts.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        for line in layout {
+            match is_comment(line) {
+                true => println!("{}", line.yellow()),
+                false => println!("{}", line),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn sections_command(file_paths: Vec<PathBuf>, load: &LoadOpts) -> Result<(), Box<dyn Error>> {
+    let arena = Arena::new();
+    let mut registry = make_registry(&arena, load)?;
     // Iterate over each file path and process it
-    for file_path in file_paths {
+    for file_path in expand_paths(&mut registry, file_paths) {
         println!("{}", format!("Loading file {:?}", file_path).green().bold());
-        let buffer = fs::read(file_path)?;
-        let mut machine_file = MachineFile::parse(&buffer)?;
-        let debug = machine_file.get_addr2line().ok();
+        let machine_file = registry.get_machine(file_path.clone())?;
+        print_debug_file(machine_file)?;
+        let debug = machine_file.get_locator().ok();
+        let symbols = machine_file.get_symbols();
         let cs = create_capstone(machine_file.obj.architecture())?;
 
-        for section in &mut machine_file.sections {
+        for section in machine_file.sections.iter() {
             match section {
                 Section::Code(code_section) => {
                     // lazy.disasm(&machine_file.obj.architecture())?;
@@ -211,16 +501,22 @@ pub fn sections_command(file_paths: Vec<PathBuf>) -> Result<(), Box<dyn Error>>
 
                     code_section.map_asm(&cs, &mut |instruction: &InstructionDetail| {
                         let func_name = match &debug {
-                            None => None,
-                            Some(ctx) => resolve_func_name(ctx, instruction.address),
+                            None => symbols.label(instruction.address),
+                            Some(locator) => {
+                                resolve_func_name(locator, &symbols, instruction.address)
+                            }
                         };
                         // func_name.as_mut().map(|x| x.push_str(" "));
                         // println!("  {}", instruction);
+                        let shown = machine_file
+                            .layout
+                            .shown_address(instruction.address)
+                            .wrapping_add(load.base);
                         println!(
                             "  {:#010x}: {:<6} {:<30} {}",
-                            instruction.address,
+                            shown,
                             instruction.mnemonic,
-                            instruction.op_str,
+                            code_section.shown_op_str(&cs, instruction, shown)?,
                             func_name.as_deref().unwrap_or("")
                         );
                         Ok(())
@@ -242,22 +538,26 @@ pub fn sections_command(file_paths: Vec<PathBuf>) -> Result<(), Box<dyn Error>>
     Ok(())
 }
 
-pub fn view_sources_command(file_paths: Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
-    let mut source_files: HashSet<Box<str>> = HashSet::new();
-    for file_path in file_paths {
+pub fn view_sources_command(
+    file_paths: Vec<PathBuf>,
+    load: &LoadOpts,
+) -> Result<(), Box<dyn Error>> {
+    let arena = Arena::new();
+    let mut registry = make_registry(&arena, load)?;
+    let mut source_files: HashSet<PathBuf> = HashSet::new();
+    for file_path in expand_paths(&mut registry, file_paths) {
         println!("{}", format!("Loading file {:?}", file_path).green().bold());
-        let buffer = fs::read(file_path)?;
-        let machine_file = MachineFile::parse(&buffer)?;
-        let ctx = machine_file.get_addr2line()?;
+        let machine_file = registry.get_machine(file_path.clone())?;
+        print_debug_file(machine_file)?;
+        let locator = machine_file.get_locator()?;
         for section in machine_file.sections.iter() {
             let Section::Code(code) = section else {
                 continue;
             };
 
-            let mut locs = ctx.find_location_range(code.address, code.get_high())?;
-            while let Some((_, _, loc)) = FallibleIterator::next(&mut locs)? {
+            for (_, _, loc) in locator.find_location_range(code.address, code.get_high())? {
                 if let Some(file) = loc.file {
-                    source_files.insert(file.into());
+                    source_files.insert(machine_file.source_path(file).into());
                 }
             }
         }
@@ -278,6 +578,7 @@ pub fn view_source_command(
     look_all: bool,
     walk: bool,
     selections: Vec<FileSelection>,
+    load: &LoadOpts,
 ) -> Result<(), Box<dyn Error>> {
     //we allow look_all and selections at the same time we simply ignore selctions
 
@@ -286,7 +587,7 @@ pub fn view_source_command(
     //     return Err("Can only walk in 1 file at a time".into());
     // }
 
-    if walk && selections.len() == 0 {
+    if walk && selections.is_empty() {
         return Err("No walk selection provided".into());
     }
 
@@ -294,32 +595,46 @@ pub fn view_source_command(
     let obj_file: Arc<Path> = file_path.into();
     let asm_arena = Arena::new();
     let code_arena = Arena::new();
-    let mut registry = FileRegistry::new(&asm_arena);
+    let mut registry = make_registry(&asm_arena, load)?;
     let mut code_files = CodeRegistry::new(&mut registry, &code_arena);
-    let machine_file = code_files.visit_machine_file(obj_file.clone())?;
-    let ctx = machine_file.get_addr2line()?;
 
     // Populate a unique list of source files in the order they appear
     let mut source_files_set: HashSet<PathBuf> = HashSet::new();
-    for section in machine_file.sections.iter() {
-        let Section::Code(code) = section else {
-            continue;
-        };
+    for path in code_files.asm.expand(obj_file.clone()) {
+        let machine_file = code_files.visit_machine_file(path)?;
+        let locator = machine_file.get_locator()?;
+        for section in machine_file.sections.iter() {
+            let Section::Code(code) = section else {
+                continue;
+            };
 
-        let mut locs = ctx.find_location_range(code.address, code.get_high())?;
-        while let Some((_, _, loc)) = FallibleIterator::next(&mut locs)? {
-            if let Some(file) = loc.file {
-                source_files_set.insert(file.into());
+            for (_, _, loc) in locator.find_location_range(code.address, code.get_high())? {
+                if let Some(file) = loc.file {
+                    source_files_set.insert(machine_file.source_path(file).into());
+                }
             }
         }
     }
 
+    //a path missing here is only fine if the binary embedded that source
+    for selection in &selections {
+        let FileSelection::Path(path) = selection else {
+            continue;
+        };
+        let embedded = find_source_file(&source_files_set, path)
+            .is_some_and(|file| code_files.asm.embedded_source(file).is_some());
+        if !path.exists() && !embedded {
+            return Err(
+                format!("'{}' is not a valid index or existing path", path.display()).into(),
+            );
+        }
+    }
+
     let mut source_files: Vec<&Path> = source_files_set.iter().map(|p| p.as_path()).collect();
     source_files.sort();
 
     if walk {
-        machine_file.get_lines_map()?;
-        machine_file.get_capstone()?;
+        let WalkFiles { symbols, root } = load_walk_files(&mut code_files, obj_file.clone())?;
 
         let file_path = match &selections[0] {
             FileSelection::Index(i) => {
@@ -331,7 +646,7 @@ pub fn view_source_command(
                 }
             }
             FileSelection::Path(path) => {
-                if let Some(ans) = source_files_set.get(path) {
+                if let Some(ans) = find_source_file(&source_files_set, path) {
                     ans
                 } else {
                     println!(
@@ -342,13 +657,19 @@ pub fn view_source_command(
                 }
             }
         };
-        let file_path = Path::new(file_path.into());
+        let file_path = Path::new(file_path);
         let parent = file_path
             .parent()
             .ok_or("No parent dir to path")?
             .to_path_buf();
 
-        let mut state = GlobalState::start_from(parent.into())?;
+        //an embedded source has no directory here to go back to
+        let mut state = match (parent.is_dir(), root) {
+            (true, _) => GlobalState::start_from(parent.into())?,
+            (false, Some(dir)) => GlobalState::start_from(dir)?,
+            (false, None) => GlobalState::start()?,
+        };
+        state.symbols = symbols;
         let mut session = TerminalSession::new(&mut state)?;
 
         let code_file = code_files.get_source_file(file_path.into(), true)?;
@@ -404,7 +725,7 @@ pub fn view_source_command(
                     }
                 }
                 FileSelection::Path(path) => {
-                    if let Some(file) = source_files_set.get(&path) {
+                    if let Some(file) = find_source_file(&source_files_set, &path) {
                         files_to_display.push(file);
                     } else {
                         println!(
@@ -419,18 +740,37 @@ pub fn view_source_command(
 
     // Display the contents of each file in `files_to_display`
     for file in files_to_display {
-        display_file_contents(file)?;
+        display_file_contents(file, code_files.asm)?;
     }
 
     Ok(())
 }
 
+// selections are canonical paths, remapped sources may not be
+fn find_source_file<'s>(source_files: &'s HashSet<PathBuf>, path: &Path) -> Option<&'s Path> {
+    if let Some(file) = source_files.get(path) {
+        return Some(file);
+    }
+    source_files
+        .iter()
+        .find(|file| fs::canonicalize(file).is_ok_and(|c| c == path))
+        .map(|file| file.as_path())
+}
+
 // Helper function to display the contents of a file with line numbers
-fn display_file_contents(file_path: &Path) -> Result<(), Box<dyn Error>> {
+fn display_file_contents(file_path: &Path, registry: &FileRegistry) -> Result<(), Box<dyn Error>> {
     match fs::canonicalize(file_path) {
         Ok(file) => match fs::read_to_string(&file) {
             Ok(source_text) => {
                 println!("Contents of {:?}:", file);
+                if registry.is_stale(file_path, &source_text) {
+                    println!(
+                        "{}",
+                        "WARNING: the file was edited since the binary was built (MD5 mismatch), lines may not match"
+                            .red()
+                            .bold()
+                    );
+                }
                 for (i, line) in source_text.lines().enumerate() {
                     println!("{:4} {}", i + 1, line);
                 }
@@ -439,30 +779,37 @@ fn display_file_contents(file_path: &Path) -> Result<(), Box<dyn Error>> {
                 println!("{} reading {:?}: {}", "FAILED".red(), file, e);
             }
         },
-        Err(_) => {
-            println!("{}", format!("{:?} does not exist", file_path).red());
-        }
+        Err(_) => match registry.embedded_source(file_path) {
+            Some(source_text) => {
+                println!("Contents of {:?} (embedded in the binary):", file_path);
+                for (i, line) in source_text.lines().enumerate() {
+                    println!("{:4} {}", i + 1, line);
+                }
+            }
+            None => println!("{}", format!("{:?} does not exist", file_path).red()),
+        },
     }
     Ok(())
 }
 
-pub fn functions_command(file_paths: Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+pub fn functions_command(file_paths: Vec<PathBuf>, load: &LoadOpts) -> Result<(), Box<dyn Error>> {
     let arena = Arena::new();
-    let mut registry = FileRegistry::new(&arena);
+    let mut registry = make_registry(&arena, load)?;
 
     // Iterate over each file path and process it
-    for file_path in file_paths {
+    for file_path in expand_paths(&mut registry, file_paths) {
         let mut seen = HashSet::new();
 
         println!("{}", format!("functions in {:?}", file_path).green().bold());
-        let machine_file = registry.get_machine(file_path.into())?;
-        let ctx = machine_file.get_addr2line()?;
+        let machine_file = registry.get_machine(file_path.clone())?;
+        let locator = machine_file.get_locator()?;
+        let symbols = machine_file.get_symbols();
         let cs = create_capstone(machine_file.obj.architecture())?;
 
         for section in &machine_file.sections.clone() {
             if let Section::Code(code_section) = section {
                 code_section.map_asm(&cs, &mut |ins| {
-                    map_funcs(&ctx, &mut registry, ins.address, |func| {
+                    map_funcs(&locator, &mut registry, &symbols, ins.address, |func| {
                         if seen.insert(func.to_string()) {
                             println!("{} {}", seen.len().to_string().blue(), func);
                         }
@@ -476,6 +823,339 @@ pub fn functions_command(file_paths: Vec<PathBuf>) -> Result<(), Box<dyn Error>>
     Ok(())
 }
 
+/// Lists the functions an unwinder would get stuck in: the ones no FDE covers, fully or at all.
+///
+/// Functions come from the symbol table, so hand written asm (crti.S, start.S) is checked too.
+pub fn cfi_command(file_paths: Vec<PathBuf>, load: &LoadOpts) -> Result<(), Box<dyn Error>> {
+    let arena = Arena::new();
+    let mut registry = make_registry(&arena, load)?;
+
+    for file_path in expand_paths(&mut registry, file_paths) {
+        println!("{}", format!("Loading file {:?}", file_path).green().bold());
+        let machine_file = registry.get_machine(file_path.clone())?;
+        let Some(frames) = machine_file.get_frames() else {
+            println!("{}", "no .eh_frame or .debug_frame".red());
+            continue;
+        };
+        let fdes = frames.fde_ranges()?;
+        let symbols = machine_file.get_symbols();
+
+        for section in &machine_file.sections {
+            let Section::Code(code_section) = section else {
+                continue;
+            };
+            let end = code_section.address + code_section.data.len() as u64;
+            let funcs: Vec<_> = symbols
+                .iter()
+                .filter(|s| code_section.address <= s.address && s.address < end)
+                .collect();
+            //stubs like .plt have no symbols to check
+            if funcs.is_empty() {
+                continue;
+            }
+            println!("{}", section.name());
+
+            let mut covered = 0;
+            for func in &funcs {
+                let func_end = func.address + func.size.max(1);
+                let bytes = covered_bytes(&fdes, func.address, func_end);
+                let shown = machine_file.layout.shown_address(func.address);
+                let address = format!("{:#010x}", shown.wrapping_add(load.base));
+                if bytes == 0 {
+                    println!(
+                        "  {}: {} {}",
+                        address.bold(),
+                        func.name.cyan(),
+                        "has no FDE".red()
+                    );
+                } else if bytes < func_end - func.address {
+                    println!(
+                        "  {}: {} {}",
+                        address.bold(),
+                        func.name.cyan(),
+                        format!(
+                            "FDEs cover {:#x} of its {:#x} bytes",
+                            bytes,
+                            func_end - func.address
+                        )
+                        .yellow()
+                    );
+                } else {
+                    covered += 1;
+                }
+            }
+
+            let summary = format!("  {} of {} functions have an FDE", covered, funcs.len());
+            if covered == funcs.len() {
+                println!("{}", summary.green());
+            } else {
+                println!("{}", summary.red());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// how much of `begin..end` the sorted FDE ranges cover
+fn covered_bytes(fdes: &[gimli::Range], begin: u64, end: u64) -> u64 {
+    let mut covered = 0;
+    let mut cursor = begin;
+    for fde in fdes.iter().filter(|r| r.begin < end && r.end > begin) {
+        let from = fde.begin.max(cursor);
+        let to = fde.end.min(end);
+        if to > from {
+            covered += to - from;
+            cursor = to;
+        }
+    }
+    covered
+}
+
+/// Prints where each address comes from, the way `addr2line -fiC` does.
+///
+/// Addresses are runtime ones, `load.base` (the load bias) is taken off before the lookup.
+/// With no addresses given they are read from stdin, one or more per line.
+pub fn symbolize_command(
+    file_path: &Path,
+    addresses: Vec<u64>,
+    json: bool,
+    load: &LoadOpts,
+) -> Result<(), Box<dyn Error>> {
+    let base = load.base;
+    let arena = Arena::new();
+    let mut registry = make_registry(&arena, load)?;
+    let machine_file = registry.get_machine(file_path.into())?;
+    let locator = machine_file.get_locator()?;
+    let symbols = machine_file.get_symbols();
+    let remap = machine_file.source_remap.clone();
+    let layout = machine_file.layout.clone();
+
+    let mut symbolize = |address: u64| -> Result<(), Box<dyn Error>> {
+        let Some(file_address) = address.checked_sub(base) else {
+            eprintln!("{:#x} is below the load bias {:#x}", address, base);
+            return Ok(());
+        };
+        //an object's addresses are section offsets, the first section holding one is used
+        let placed = layout
+            .placed_addresses(file_address)
+            .first()
+            .copied()
+            .unwrap_or(file_address);
+        let frames = source_frames(&locator, &mut registry, &symbols, &remap, placed);
+
+        if json {
+            println!("{}", frames_json(address, file_address, &frames)?);
+            return Ok(());
+        }
+
+        println!("{}", format!("{:#x}", address).bold());
+        for (i, frame) in frames.iter().enumerate() {
+            let prefix = if i == 0 { "" } else { " (inlined by) " };
+            println!("{}{}", prefix, format_frame(frame));
+        }
+        Ok(())
+    };
+
+    if !addresses.is_empty() {
+        for address in addresses {
+            symbolize(address)?;
+        }
+        return Ok(());
+    }
+
+    for line in std::io::stdin().lines() {
+        for word in line?.split_whitespace() {
+            match parse_address(word) {
+                Ok(address) => symbolize(address)?,
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the inline chain at `address` with source paths rewritten to local ones
+fn source_frames<'a, 'b: 'a>(
+    locator: &DebugLocator<'a>,
+    registry: &mut FileRegistry<'b>,
+    symbols: &SymbolIndex,
+    remap: &PathRemap,
+    address: u64,
+) -> Vec<SourceFrame> {
+    let mut frames = find_source_frames(locator, registry, symbols, address);
+    for frame in &mut frames {
+        if let Some(file) = &frame.file {
+            frame.file = Some(remap.apply(Path::new(file)).display().to_string());
+        }
+    }
+    frames
+}
+
+/// `func at file:line:column`
+fn format_frame(frame: &SourceFrame) -> String {
+    let function = frame.function.as_deref().unwrap_or("<unknown>");
+    let file = frame.file.as_deref().unwrap_or("<unknown>");
+    let line = frame
+        .line
+        .map_or("<unknown>".to_string(), |l| l.to_string());
+    let column = frame.column.map(|c| format!(":{}", c)).unwrap_or_default();
+    format!(
+        "{} at {}:{}{}",
+        function.cyan(),
+        file.yellow(),
+        line.blue(),
+        column.blue()
+    )
+}
+
+/// A binary `annotate-log` resolves addresses against
+struct LogBinary<'a> {
+    path: Arc<Path>,
+    /// file names a log may call it by, a member is also known by its container's name
+    names: Vec<std::ffi::OsString>,
+    locator: DebugLocator<'a>,
+    symbols: Arc<SymbolIndex>,
+    remap: Rc<PathRemap>,
+    /// what `module+offset` offsets count from
+    module_base: u64,
+}
+
+/// ELF offsets count from the load bias, PE ones are RVAs and Mach-O ones start at the header
+fn module_base(obj: &object::File) -> u64 {
+    match obj.format() {
+        object::BinaryFormat::MachO => obj
+            .segments()
+            .find(|segment| segment.name().ok().flatten() == Some("__TEXT"))
+            .map_or(0, |segment| segment.address()),
+        _ => obj.relative_address_base(),
+    }
+}
+
+/// Copies a log from stdin to stdout with the source of every address it mentions appended.
+///
+/// `module+offset` forms are matched to the binaries by file name, a line that has one of those
+/// ignores its raw addresses. Raw addresses have `load.base` taken off and go to whichever binary
+/// has code there.
+pub fn annotate_log_command(
+    file_paths: Vec<PathBuf>,
+    load: &LoadOpts,
+) -> Result<(), Box<dyn Error>> {
+    let base = load.base;
+    let arena = Arena::new();
+    let mut registry = make_registry(&arena, load)?;
+
+    let mut binaries = Vec::new();
+    for path in expand_paths(&mut registry, file_paths) {
+        let machine_file = registry.get_machine(path.clone())?;
+        let names = [Some(&*path), machine_file.archive.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|p| p.file_name().map(|name| name.to_os_string()))
+            .collect();
+        binaries.push(LogBinary {
+            names,
+            locator: machine_file.get_locator()?,
+            symbols: machine_file.get_symbols(),
+            remap: machine_file.source_remap.clone(),
+            module_base: module_base(&machine_file.obj),
+            path,
+        });
+    }
+
+    //logs without `#N` get their frames counted, a line with no address ends the backtrace
+    let mut next_frame = 0;
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        let addresses = scan_line(&line);
+        let frame = frame_number(&line).unwrap_or(next_frame);
+        next_frame = if addresses.is_empty() { 0 } else { frame + 1 };
+
+        let mut found: Vec<(&LogBinary, u64)> = addresses
+            .iter()
+            .filter_map(|address| match address {
+                LogAddress::Module { module, offset } => {
+                    let name = Path::new(module).file_name()?;
+                    let binary = binaries
+                        .iter()
+                        .find(|b| b.names.iter().any(|n| n == name))?;
+                    Some((binary, binary.module_base.wrapping_add(*offset)))
+                }
+                LogAddress::Raw(_) => None,
+            })
+            .collect();
+        if found.is_empty() {
+            for address in &addresses {
+                let LogAddress::Raw(address) = address else {
+                    continue;
+                };
+                let Some(address) = address.checked_sub(base) else {
+                    continue;
+                };
+                let binary = binaries.iter().find(|b| {
+                    registry
+                        .get_machine(b.path.clone())
+                        .is_ok_and(|machine| machine.has_code_at(address))
+                });
+                found.extend(binary.map(|b| (b, address)));
+            }
+        }
+
+        let mut annotated = line.clone();
+        for (binary, address) in found {
+            //a return address can be past the end of a function that calls a noreturn one
+            let probe = if frame == 0 {
+                address
+            } else {
+                address.saturating_sub(1)
+            };
+            let frames = source_frames(
+                &binary.locator,
+                &mut registry,
+                &binary.symbols,
+                &binary.remap,
+                probe,
+            );
+            if frames
+                .iter()
+                .all(|frame| frame.function.is_none() && frame.file.is_none())
+            {
+                continue;
+            }
+            let chain: Vec<String> = frames.iter().map(format_frame).collect();
+            write!(
+                annotated,
+                "  {} {}",
+                "->".green(),
+                chain.join(", inlined by ")
+            )
+            .ok();
+        }
+        println!("{}", annotated);
+    }
+    Ok(())
+}
+
+/// one line of JSON per address, missing values are null
+fn frames_json(
+    address: u64,
+    file_address: u64,
+    frames: &[SourceFrame],
+) -> Result<String, Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct Symbolized<'a> {
+        address: String,
+        file_address: String,
+        frames: &'a [SourceFrame],
+    }
+
+    Ok(serde_json::to_string(&Symbolized {
+        address: format!("{:#x}", address),
+        file_address: format!("{:#x}", file_address),
+        frames,
+    })?)
+}
+
 pub fn config_paths_command() -> Result<(), Box<dyn Error>> {
     let w = get_walk_config_path();
     let walk_path = match w {
@@ -483,5 +1163,12 @@ pub fn config_paths_command() -> Result<(), Box<dyn Error>> {
         None => "<does not exist>".into(),
     };
     println!("  walk confing {}", walk_path);
+
+    let d = get_debug_config_path();
+    let debug_path = match d {
+        Some(ref p) => p.to_string_lossy(),
+        None => "<does not exist>".into(),
+    };
+    println!("  debug config {}", debug_path);
     Ok(())
 }
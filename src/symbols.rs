@@ -0,0 +1,122 @@
+use crate::relocatable::SectionLayout;
+use object::{BinaryFormat, Object, ObjectSection, ObjectSymbol, SectionIndex, SymbolKind};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// A function from the symbol table
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub address: u64,
+    pub size: u64,
+    /// demangled name
+    pub name: Box<str>,
+    section: Option<SectionIndex>,
+}
+
+/// Code symbols of a file sorted by address, used when DWARF has nothing to say
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    symbols: Box<[Symbol]>,
+}
+
+impl SymbolIndex {
+    pub fn new(obj: &object::File, layout: &SectionLayout) -> Self {
+        let format = obj.format();
+        let mut symbols: Vec<Symbol> = obj
+            .symbols()
+            .chain(obj.dynamic_symbols())
+            .filter(|s| s.kind() == SymbolKind::Text && s.is_definition())
+            .filter_map(|s| {
+                let name = s.name().ok().filter(|n| !n.is_empty())?;
+                Some(Symbol {
+                    address: layout.symbol_address(&s),
+                    size: s.size(),
+                    name: demangle_symbol(name, format),
+                    section: s.section_index(),
+                })
+            })
+            .collect();
+
+        //aliases and the dynamic copy of a global share an address, keep one with a size
+        symbols.sort_by_key(|s| (s.address, s.size == 0));
+        symbols.dedup_by_key(|s| s.address);
+
+        //mach-o (and some hand written asm) has no sizes, those run to the next symbol or the section end
+        for i in 0..symbols.len() {
+            if symbols[i].size != 0 {
+                continue;
+            }
+            let Some(section) = symbols[i].section else {
+                continue;
+            };
+
+            let end = match symbols.get(i + 1) {
+                Some(next) if next.section == Some(section) => Some(next.address),
+                _ => obj
+                    .section_by_index(section)
+                    .ok()
+                    .map(|s| layout.section_address(&s) + s.size()),
+            };
+            if let Some(end) = end {
+                symbols[i].size = end.saturating_sub(symbols[i].address);
+            }
+        }
+
+        SymbolIndex {
+            symbols: symbols.into(),
+        }
+    }
+
+    /// one index over several files that do not share addresses (the members of an archive)
+    pub fn merged(indexes: &[Arc<SymbolIndex>]) -> Self {
+        let mut symbols: Vec<Symbol> = indexes
+            .iter()
+            .flat_map(|index| index.symbols.iter().cloned())
+            .collect();
+        symbols.sort_by_key(|s| s.address);
+        SymbolIndex {
+            symbols: symbols.into(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Symbol> {
+        self.symbols.iter()
+    }
+
+    /// the symbol whose range holds `address`
+    pub fn find(&self, address: u64) -> Option<&Symbol> {
+        let idx = self
+            .symbols
+            .partition_point(|s| s.address <= address)
+            .checked_sub(1)?;
+        let sym = &self.symbols[idx];
+        (address - sym.address < sym.size.max(1)).then_some(sym)
+    }
+
+    /// objdump style `<func+0x10>`
+    pub fn label(&self, address: u64) -> Option<String> {
+        let sym = self.find(address)?;
+        Some(match address - sym.address {
+            0 => format!("<{}>", sym.name),
+            offset => format!("<{}+{:#x}>", sym.name, offset),
+        })
+    }
+}
+
+fn demangle_symbol(name: &str, format: BinaryFormat) -> Box<str> {
+    //mach-o puts an extra '_' in front of every C level name
+    let name = match format {
+        BinaryFormat::MachO => name.strip_prefix('_').unwrap_or(name),
+        _ => name,
+    };
+    //plain C names would go through the itanium demangler as types (`f` comes out as `float`)
+    let mangled = ["_Z", "_R", "?"].iter().any(|p| name.starts_with(p));
+    if !mangled {
+        return name.into();
+    }
+    addr2line::demangle_auto(Cow::Borrowed(name), None).into()
+}
@@ -0,0 +1,616 @@
+use crate::file_parser::EStr;
+use gimli::{
+    constants, AttributeValue, DebuggingInformationEntry, DwTag, Dwarf, Endianity, Reader, Section,
+    Unit, UnitOffset, UnitType,
+};
+use std::error::Error;
+
+/// how deep type references are followed before giving up (they can be cyclic through pointers)
+const MAX_DEPTH: usize = 16;
+
+/// Finds every struct, class, union or enum called `name` and prints its layout the way pahole does.
+///
+/// `name` can be qualified (`ns::Foo`), only the last part is matched. A type that shows up the
+/// same in several compile units is only printed once.
+pub fn find_type_layouts(
+    dwarf: &Dwarf<EStr<'_>>,
+    name: &str,
+) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    let wanted = name.rsplit("::").next().unwrap_or(name);
+    let mut ans: Vec<Vec<String>> = Vec::new();
+
+    let units = all_units(dwarf)?;
+    let sup_units = match dwarf.sup() {
+        Some(sup) => all_units(sup)?,
+        None => Vec::new(),
+    };
+    let sup = dwarf.sup().map(|sup| (sup, &sup_units[..]));
+
+    for unit in &units {
+        let printer = LayoutPrinter {
+            dwarf,
+            unit,
+            units: &units,
+            sup,
+        };
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if !is_type_tag(entry.tag()) || is_declaration(entry) {
+                continue;
+            }
+            if printer.name(entry).as_deref() != Some(wanted) {
+                continue;
+            }
+            let mut lines = Vec::new();
+            printer.layout(entry.offset(), &mut lines)?;
+            if !ans.contains(&lines) {
+                ans.push(lines);
+            }
+        }
+    }
+    Ok(ans)
+}
+
+/// the compile units then the `.debug_types` units
+fn all_units<'data>(dwarf: &Dwarf<EStr<'data>>) -> Result<Vec<Unit<EStr<'data>>>, Box<dyn Error>> {
+    let mut units = Vec::new();
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        units.push(dwarf.unit(header)?);
+    }
+    let mut headers = dwarf.type_units();
+    while let Some(header) = headers.next()? {
+        units.push(dwarf.unit(header)?);
+    }
+    Ok(units)
+}
+
+fn is_type_tag(tag: DwTag) -> bool {
+    matches!(
+        tag,
+        constants::DW_TAG_structure_type
+            | constants::DW_TAG_class_type
+            | constants::DW_TAG_union_type
+            | constants::DW_TAG_enumeration_type
+    )
+}
+
+fn is_declaration(entry: &DebuggingInformationEntry<EStr<'_>>) -> bool {
+    matches!(
+        entry.attr_value(constants::DW_AT_declaration),
+        Ok(Some(AttributeValue::Flag(true)))
+    )
+}
+
+/// A data member (or base class) of a struct
+struct Member<'d, 'data> {
+    name: String,
+    type_name: String,
+    type_at: Option<(LayoutPrinter<'d, 'data>, UnitOffset)>,
+    /// from the start of the outermost struct, offsets are shown that way too
+    bit_offset: u64,
+    bit_size: Option<u64>,
+    size: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+struct LayoutPrinter<'d, 'data> {
+    dwarf: &'d Dwarf<EStr<'data>>,
+    unit: &'d Unit<EStr<'data>>,
+    /// every unit of `dwarf`, type references can leave `unit`
+    units: &'d [Unit<EStr<'data>>],
+    /// the dwz supplementary file and its units
+    sup: Option<(&'d Dwarf<EStr<'data>>, &'d [Unit<EStr<'data>>])>,
+}
+
+impl<'d, 'data> LayoutPrinter<'d, 'data> {
+    /// the layout of the type at `offset` followed by the named types nested in it
+    fn layout(&self, offset: UnitOffset, lines: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+        let entry = self.unit.entry(offset)?;
+        if entry.tag() == constants::DW_TAG_enumeration_type {
+            self.enum_layout(&entry, lines)?;
+            return Ok(());
+        }
+
+        let size = self.type_size(offset, 0);
+        lines.push(format!("{} {{", self.type_name(offset, 0)));
+        let stats = self.members_layout(offset, 0, 1, lines)?;
+        lines.push(String::new());
+
+        let size_text = size.map_or("?".to_string(), |s| s.to_string());
+        lines.push(format!(
+            "    /* size: {}, members: {} */",
+            size_text, stats.members
+        ));
+        if stats.holes > 0 {
+            lines.push(format!(
+                "    /* sum members: {}, holes: {}, sum holes: {} */",
+                bytes(stats.member_bits),
+                stats.holes,
+                bytes(stats.hole_bits)
+            ));
+        }
+        if let Some(size) = size {
+            let padding = (size * 8).saturating_sub(stats.end_bits);
+            if padding > 0 && entry.tag() != constants::DW_TAG_union_type {
+                lines.push(format!("    /* padding: {} */", bytes(padding)));
+            }
+        }
+        if let Some(align) = self.type_align(offset, 0) {
+            lines.push(format!("    /* alignment: {} */", align));
+        }
+        lines.push("};".to_string());
+
+        //named types declared inside this one
+        let mut tree = self.unit.entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let child = child.entry();
+            if is_type_tag(child.tag()) && !is_declaration(child) && self.name(child).is_some() {
+                lines.push(String::new());
+                self.layout(child.offset(), lines)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the members of a struct or union, anonymous ones are expanded in place
+    fn members_layout(
+        &self,
+        offset: UnitOffset,
+        base_bits: u64,
+        indent: usize,
+        lines: &mut Vec<String>,
+    ) -> Result<LayoutStats, Box<dyn Error>> {
+        let pad = "    ".repeat(indent);
+        let is_union = self.unit.entry(offset)?.tag() == constants::DW_TAG_union_type;
+        let mut stats = LayoutStats {
+            end_bits: base_bits,
+            ..Default::default()
+        };
+
+        for member in self.members(offset, base_bits)? {
+            let member_bits = member.bit_size.or(member.size.map(|s| s * 8)).unwrap_or(0);
+            if !is_union && member.bit_offset > stats.end_bits {
+                let hole = member.bit_offset - stats.end_bits;
+                stats.holes += 1;
+                stats.hole_bits += hole;
+                lines.push(format!(
+                    "{}/* XXX {} hole, try to pack */",
+                    pad,
+                    bytes(hole)
+                ));
+            }
+
+            let anonymous = member.name.is_empty()
+                && member
+                    .type_at
+                    .and_then(|(printer, t)| printer.unit.entry(t).ok())
+                    .is_some_and(|t| {
+                        matches!(
+                            t.tag(),
+                            constants::DW_TAG_structure_type
+                                | constants::DW_TAG_union_type
+                                | constants::DW_TAG_class_type
+                        )
+                    });
+            match member.type_at {
+                Some((printer, type_offset)) if anonymous => {
+                    lines.push(format!("{}{} {{", pad, printer.type_name(type_offset, 0)));
+                    printer.members_layout(type_offset, member.bit_offset, indent + 1, lines)?;
+                    lines.push(format!(
+                        "{}}}; /* {:>5} {:>5} */",
+                        pad,
+                        member.bit_offset / 8,
+                        member.size.map_or("?".to_string(), |s| s.to_string())
+                    ));
+                }
+                _ => {
+                    let declaration = match member.bit_size {
+                        Some(bits) => format!("{}:{};", member.name, bits),
+                        None => format!("{};", member.name),
+                    };
+                    let position = match member.bit_size {
+                        Some(_) => {
+                            format!("{:>5}:{:<2}", member.bit_offset / 8, member.bit_offset % 8)
+                        }
+                        None => format!("{:>5}   ", member.bit_offset / 8),
+                    };
+                    let size = member.size.map_or("?".to_string(), |s| s.to_string());
+                    lines.push(format!(
+                        "{}{:<28} {:<24} /* {} {:>5} */",
+                        pad, member.type_name, declaration, position, size
+                    ));
+                }
+            }
+
+            stats.members += 1;
+            stats.member_bits += member_bits;
+            stats.end_bits = stats.end_bits.max(member.bit_offset + member_bits);
+        }
+        Ok(stats)
+    }
+
+    fn members(
+        &self,
+        offset: UnitOffset,
+        base_bits: u64,
+    ) -> Result<Vec<Member<'d, 'data>>, Box<dyn Error>> {
+        let mut ans = Vec::new();
+        let mut tree = self.unit.entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            let inheritance = entry.tag() == constants::DW_TAG_inheritance;
+            if entry.tag() != constants::DW_TAG_member && !inheritance {
+                continue;
+            }
+            //static members are declarations, they take no room
+            if is_declaration(entry) || entry.attr_value(constants::DW_AT_external)?.is_some() {
+                continue;
+            }
+
+            let type_at = self.type_ref(entry);
+            let (type_name, dims) = match type_at {
+                Some((printer, t)) => printer.declarator(t),
+                None => (missing_type(entry), String::new()),
+            };
+            let name = match (self.name(entry), inheritance) {
+                (_, true) => "<ancestor>".to_string(),
+                (Some(name), false) => name + &dims,
+                (None, false) => String::new(),
+            };
+
+            let bit_size = entry
+                .attr_value(constants::DW_AT_bit_size)?
+                .and_then(|v| v.udata_value());
+            let location = match entry.attr_value(constants::DW_AT_data_member_location)? {
+                Some(value) => member_location(self.unit, value) * 8,
+                None => 0,
+            };
+            let bit_offset = match entry.attr_value(constants::DW_AT_data_bit_offset)? {
+                Some(value) => value.udata_value().unwrap_or(0),
+                None => match (entry.attr_value(constants::DW_AT_bit_offset)?, bit_size) {
+                    //DWARF 4 counts from the most significant bit of a `DW_AT_byte_size` storage unit
+                    (Some(from_msb), Some(bits)) => {
+                        let from_msb = from_msb.udata_value().unwrap_or(0);
+                        let storage = entry
+                            .attr_value(constants::DW_AT_byte_size)?
+                            .and_then(|v| v.udata_value())
+                            .or_else(|| type_at.and_then(|(printer, t)| printer.type_size(t, 0)))
+                            .unwrap_or(0);
+                        if self.dwarf.debug_info.reader().endian().is_little_endian() {
+                            (location + storage * 8).saturating_sub(from_msb + bits)
+                        } else {
+                            location + from_msb
+                        }
+                    }
+                    _ => location,
+                },
+            };
+
+            ans.push(Member {
+                name,
+                type_name,
+                type_at,
+                bit_offset: base_bits + bit_offset,
+                bit_size,
+                size: type_at.and_then(|(printer, t)| printer.type_size(t, 0)),
+            });
+        }
+        Ok(ans)
+    }
+
+    fn enum_layout(
+        &self,
+        entry: &DebuggingInformationEntry<EStr<'data>>,
+        lines: &mut Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        let size = self
+            .type_size(entry.offset(), 0)
+            .map_or("?".to_string(), |s| s.to_string());
+        lines.push(format!(
+            "{} {{ /* size: {} */",
+            self.type_name(entry.offset(), 0),
+            size
+        ));
+        let mut tree = self.unit.entries_tree(Some(entry.offset()))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let child = child.entry();
+            if child.tag() != constants::DW_TAG_enumerator {
+                continue;
+            }
+            let name = self.name(child).unwrap_or_default();
+            let value = match child.attr_value(constants::DW_AT_const_value)? {
+                Some(AttributeValue::Sdata(v)) => v.to_string(),
+                Some(value) => value.udata_value().map_or("?".into(), |v| v.to_string()),
+                None => "?".to_string(),
+            };
+            lines.push(format!("    {} = {},", name, value));
+        }
+        lines.push("};".to_string());
+        Ok(())
+    }
+
+    fn name(&self, entry: &DebuggingInformationEntry<EStr<'data>>) -> Option<String> {
+        let value = entry.attr_value(constants::DW_AT_name).ok()??;
+        let name = self.dwarf.attr_string(self.unit, value).ok()?;
+        Some(name.to_string_lossy().into_owned())
+    }
+
+    /// the type and what goes after the member name, which is where C puts array sizes
+    fn declarator(&self, offset: UnitOffset) -> (String, String) {
+        match self.unit.entry(offset) {
+            Ok(entry) if entry.tag() == constants::DW_TAG_array_type => {
+                let element = match self.type_ref(&entry) {
+                    Some((printer, t)) => printer.type_name(t, 1),
+                    None => missing_type(&entry),
+                };
+                (element, self.dims(offset))
+            }
+            _ => (self.type_name(offset, 0), String::new()),
+        }
+    }
+
+    fn dims(&self, offset: UnitOffset) -> String {
+        self.array_counts(offset)
+            .iter()
+            .map(|c| match c {
+                Some(c) => format!("[{}]", c),
+                None => "[]".to_string(),
+            })
+            .collect()
+    }
+
+    /// the type as C would spell it
+    fn type_name(&self, offset: UnitOffset, depth: usize) -> String {
+        let Ok(entry) = self.unit.entry(offset) else {
+            return "<bad type>".to_string();
+        };
+        if depth > MAX_DEPTH {
+            return "...".to_string();
+        }
+        let inner = || match self.type_ref(&entry) {
+            Some((printer, t)) => printer.type_name(t, depth + 1),
+            None => missing_type(&entry),
+        };
+        let named = |prefix: &str| {
+            let name = self
+                .name(&entry)
+                .unwrap_or_else(|| "<anonymous>".to_string());
+            format!("{}{}", prefix, name)
+        };
+
+        match entry.tag() {
+            constants::DW_TAG_structure_type => named("struct "),
+            constants::DW_TAG_class_type => named("class "),
+            constants::DW_TAG_union_type => named("union "),
+            constants::DW_TAG_enumeration_type => named("enum "),
+            constants::DW_TAG_pointer_type => format!("{} *", inner()),
+            constants::DW_TAG_reference_type => format!("{} &", inner()),
+            constants::DW_TAG_rvalue_reference_type => format!("{} &&", inner()),
+            constants::DW_TAG_const_type => format!("const {}", inner()),
+            constants::DW_TAG_volatile_type => format!("volatile {}", inner()),
+            constants::DW_TAG_restrict_type => format!("{} restrict", inner()),
+            constants::DW_TAG_atomic_type => format!("_Atomic {}", inner()),
+            constants::DW_TAG_array_type => format!("{}{}", inner(), self.dims(offset)),
+            constants::DW_TAG_subroutine_type => "<function>".to_string(),
+            _ => named(""),
+        }
+    }
+
+    fn type_size(&self, offset: UnitOffset, depth: usize) -> Option<u64> {
+        let entry = self.unit.entry(offset).ok()?;
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        if let Some(size) = entry.attr_value(constants::DW_AT_byte_size).ok()? {
+            return size.udata_value();
+        }
+        match entry.tag() {
+            constants::DW_TAG_pointer_type
+            | constants::DW_TAG_reference_type
+            | constants::DW_TAG_rvalue_reference_type => {
+                Some(self.unit.header.address_size() as u64)
+            }
+            constants::DW_TAG_typedef
+            | constants::DW_TAG_const_type
+            | constants::DW_TAG_volatile_type
+            | constants::DW_TAG_restrict_type
+            | constants::DW_TAG_atomic_type => {
+                let (printer, t) = self.type_ref(&entry)?;
+                printer.type_size(t, depth + 1)
+            }
+            constants::DW_TAG_array_type => {
+                let (printer, t) = self.type_ref(&entry)?;
+                let element = printer.type_size(t, depth + 1)?;
+                self.array_counts(offset)
+                    .iter()
+                    .try_fold(element, |size, count| Some(size * (*count)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// DW_AT_alignment when the compiler gave it, otherwise what the members need
+    fn type_align(&self, offset: UnitOffset, depth: usize) -> Option<u64> {
+        let entry = self.unit.entry(offset).ok()?;
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        if let Some(align) = entry.attr_value(constants::DW_AT_alignment).ok()? {
+            return align.udata_value();
+        }
+        match entry.tag() {
+            constants::DW_TAG_structure_type
+            | constants::DW_TAG_class_type
+            | constants::DW_TAG_union_type => {
+                let members = self.members(offset, 0).ok()?;
+                let align = members
+                    .iter()
+                    .filter_map(|m| {
+                        let (printer, t) = m.type_at?;
+                        printer.type_align(t, depth + 1)
+                    })
+                    .max();
+                Some(align.unwrap_or(1))
+            }
+            constants::DW_TAG_typedef
+            | constants::DW_TAG_const_type
+            | constants::DW_TAG_volatile_type
+            | constants::DW_TAG_restrict_type
+            | constants::DW_TAG_atomic_type
+            | constants::DW_TAG_array_type => {
+                let (printer, t) = self.type_ref(&entry)?;
+                printer.type_align(t, depth + 1)
+            }
+            _ => self.type_size(offset, depth).map(|size| size.max(1)),
+        }
+    }
+
+    /// Where DW_AT_type of `entry` points, in whichever unit that is.
+    ///
+    /// None for void and for references that cannot be followed.
+    fn type_ref(
+        &self,
+        entry: &DebuggingInformationEntry<EStr<'data>>,
+    ) -> Option<(Self, UnitOffset)> {
+        self.follow(entry.attr_value(constants::DW_AT_type).ok()??, 0)
+    }
+
+    fn follow(
+        &self,
+        value: AttributeValue<EStr<'data>>,
+        depth: usize,
+    ) -> Option<(Self, UnitOffset)> {
+        let (printer, offset) = match value {
+            AttributeValue::UnitRef(offset) => (*self, offset),
+            //DW_FORM_ref_addr, LTO puts a type in whichever unit had it first
+            AttributeValue::DebugInfoRef(offset) => self.units.iter().find_map(|unit| {
+                Some((
+                    self.in_unit(self.dwarf, unit),
+                    offset.to_unit_offset(&unit.header)?,
+                ))
+            })?,
+            //dwz moves the types shared between files to the supplementary one
+            AttributeValue::DebugInfoRefSup(offset) => {
+                let (dwarf, units) = self.sup?;
+                units.iter().find_map(|unit| {
+                    let printer = LayoutPrinter {
+                        dwarf,
+                        unit,
+                        units,
+                        sup: None,
+                    };
+                    Some((printer, offset.to_unit_offset(&unit.header)?))
+                })?
+            }
+            //DW_FORM_ref_sig8, the type lives in a type unit
+            AttributeValue::DebugTypesRef(signature) => {
+                self.units
+                    .iter()
+                    .find_map(|unit| match unit.header.type_() {
+                        UnitType::Type {
+                            type_signature,
+                            type_offset,
+                        }
+                        | UnitType::SplitType {
+                            type_signature,
+                            type_offset,
+                        } if type_signature == signature => {
+                            Some((self.in_unit(self.dwarf, unit), type_offset))
+                        }
+                        _ => None,
+                    })?
+            }
+            _ => return None,
+        };
+
+        //gcc leaves a stub with DW_AT_signature where a type went into a type unit
+        let entry = printer.unit.entry(offset).ok()?;
+        match entry.attr_value(constants::DW_AT_signature).ok()? {
+            Some(signature) if depth < MAX_DEPTH => printer.follow(signature, depth + 1),
+            _ => Some((printer, offset)),
+        }
+    }
+
+    fn in_unit(&self, dwarf: &'d Dwarf<EStr<'data>>, unit: &'d Unit<EStr<'data>>) -> Self {
+        LayoutPrinter {
+            dwarf,
+            unit,
+            ..*self
+        }
+    }
+
+    /// element counts of each dimension, None for flexible or unknown ones
+    fn array_counts(&self, offset: UnitOffset) -> Vec<Option<u64>> {
+        let mut ans = Vec::new();
+        let Ok(mut tree) = self.unit.entries_tree(Some(offset)) else {
+            return ans;
+        };
+        let Ok(root) = tree.root() else {
+            return ans;
+        };
+        let mut children = root.children();
+        while let Ok(Some(child)) = children.next() {
+            let child = child.entry();
+            if child.tag() != constants::DW_TAG_subrange_type {
+                continue;
+            }
+            let count = match child.attr_value(constants::DW_AT_count) {
+                Ok(Some(count)) => count.udata_value(),
+                _ => match child.attr_value(constants::DW_AT_upper_bound) {
+                    Ok(Some(upper)) => upper.udata_value().map(|u| u + 1),
+                    _ => None,
+                },
+            };
+            ans.push(count);
+        }
+        ans
+    }
+}
+
+#[derive(Default)]
+struct LayoutStats {
+    members: usize,
+    member_bits: u64,
+    holes: usize,
+    hole_bits: u64,
+    end_bits: u64,
+}
+
+/// the name of a type `LayoutPrinter::type_ref` found nothing for
+fn missing_type(entry: &DebuggingInformationEntry<EStr<'_>>) -> String {
+    match entry.attr_value(constants::DW_AT_type) {
+        Ok(None) => "void".to_string(),
+        _ => "<unresolved>".to_string(),
+    }
+}
+
+/// DW_AT_data_member_location is a constant, or a DW_OP_plus_uconst in old DWARF
+fn member_location(unit: &Unit<EStr<'_>>, value: AttributeValue<EStr<'_>>) -> u64 {
+    if let Some(offset) = value.udata_value() {
+        return offset;
+    }
+    let AttributeValue::Exprloc(expr) = value else {
+        return 0;
+    };
+    match expr.operations(unit.encoding()).next() {
+        Ok(Some(gimli::Operation::PlusConstant { value })) => value,
+        _ => 0,
+    }
+}
+
+/// `4 bytes`, or bits when it is not a whole number of bytes
+fn bytes(bits: u64) -> String {
+    match bits % 8 {
+        0 => format!("{} bytes", bits / 8),
+        _ => format!("{} bits", bits),
+    }
+}
+
+/// lines a terminal shows in a different color (the comments)
+pub fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with("/*")
+}
@@ -0,0 +1,325 @@
+use crate::cfi::FrameTable;
+use crate::dwarf_dump::register_name;
+use crate::file_parser::EStr;
+use gimli::{
+    constants, AttributeValue, CfaRule, DebuggingInformationEntry, Dwarf, EntriesTreeNode,
+    Expression, Operation, Range, Register, Unit,
+};
+use object::Object;
+use once_cell::unsync::OnceCell;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// A variable and where it lives at some address, shown as `rdi=self` or `[rsp+0x18]=buf`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarLocation {
+    pub name: String,
+    /// register or memory slot holding the value
+    pub place: String,
+}
+
+impl fmt::Display for VarLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.place, self.name)
+    }
+}
+
+/// joins locations the way `lines` and walk show them
+pub fn format_locations(vars: &[VarLocation]) -> String {
+    vars.iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+struct Variable<'a> {
+    name: String,
+    location: AttributeValue<EStr<'a>>,
+}
+
+/// A subprogram, inlined call or lexical block with the variables declared right in it
+struct Scope<'a> {
+    ranges: Vec<Range>,
+    /// DW_AT_frame_base of the enclosing subprogram
+    frame_base: Option<Expression<EStr<'a>>>,
+    variables: Vec<Variable<'a>>,
+}
+
+struct UnitScopes<'a> {
+    unit: Unit<EStr<'a>>,
+    ranges: Vec<Range>,
+    /// outer scopes come before the ones nested in them
+    scopes: OnceCell<Vec<Scope<'a>>>,
+}
+
+/// Finds the local variables in scope at an address and evaluates where they are.
+///
+/// Compile units are only walked the first time an address in them is asked for.
+pub struct VariableIndex<'a> {
+    dwarf: Arc<Dwarf<EStr<'a>>>,
+    arch: object::Architecture,
+    units: Vec<UnitScopes<'a>>,
+    /// gives the CFA that gcc's frame bases are relative to
+    frames: Option<FrameTable<'a>>,
+}
+
+impl<'a> VariableIndex<'a> {
+    pub fn new(
+        dwarf: Arc<Dwarf<EStr<'a>>>,
+        obj: &object::File<'a>,
+        frames: Option<FrameTable<'a>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut units = Vec::new();
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            let unit = dwarf.unit(header)?;
+            let mut ranges = Vec::new();
+            let mut iter = dwarf.unit_ranges(&unit)?;
+            while let Some(range) = iter.next()? {
+                ranges.push(range);
+            }
+            units.push(UnitScopes {
+                unit,
+                ranges,
+                scopes: OnceCell::new(),
+            });
+        }
+        Ok(VariableIndex {
+            dwarf,
+            arch: obj.architecture(),
+            units,
+            frames,
+        })
+    }
+
+    /// Every variable that has a location at `address`, parameters and outer scopes first
+    pub fn variables_at(&self, address: u64) -> Vec<VarLocation> {
+        //the CFA at `address` turns the DW_OP_call_frame_cfa frame base gcc uses into a stack slot
+        let cfa = self.frames.as_ref().and_then(|table| {
+            table
+                .with_row(address, |row| match row.cfa() {
+                    CfaRule::RegisterAndOffset { register, offset } => Some((*register, *offset)),
+                    CfaRule::Expression(_) => None,
+                })
+                .flatten()
+        });
+        let mut ans: Vec<VarLocation> = Vec::new();
+        for unit in &self.units {
+            if !contains(&unit.ranges, address) {
+                continue;
+            }
+            let Ok(scopes) = unit
+                .scopes
+                .get_or_try_init(|| self.collect_scopes(&unit.unit))
+            else {
+                continue;
+            };
+
+            for scope in scopes.iter().filter(|s| contains(&s.ranges, address)) {
+                let frame_base = scope
+                    .frame_base
+                    .and_then(|expr| self.frame_base(&unit.unit, expr, cfa));
+                for var in &scope.variables {
+                    let Some(place) = self.place(&unit.unit, var.location, address, &frame_base)
+                    else {
+                        continue;
+                    };
+                    //an inner variable shadows an outer one of the same name
+                    ans.retain(|v| v.name != var.name);
+                    ans.push(VarLocation {
+                        name: var.name.clone(),
+                        place,
+                    });
+                }
+            }
+        }
+        ans
+    }
+
+    fn collect_scopes(&self, unit: &Unit<EStr<'a>>) -> Result<Vec<Scope<'a>>, Box<dyn Error>> {
+        let mut scopes = Vec::new();
+        let mut tree = unit.entries_tree(None)?;
+        self.collect_node(unit, tree.root()?, None, &mut scopes)?;
+        Ok(scopes)
+    }
+
+    fn collect_node(
+        &self,
+        unit: &Unit<EStr<'a>>,
+        node: EntriesTreeNode<'_, '_, '_, EStr<'a>>,
+        frame_base: Option<Expression<EStr<'a>>>,
+        scopes: &mut Vec<Scope<'a>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let entry = node.entry();
+        let is_scope = matches!(
+            entry.tag(),
+            constants::DW_TAG_subprogram
+                | constants::DW_TAG_inlined_subroutine
+                | constants::DW_TAG_lexical_block
+        );
+
+        let mut frame_base = frame_base;
+        let mut scope = None;
+        if is_scope {
+            let mut ranges = Vec::new();
+            let mut iter = self.dwarf.die_ranges(unit, entry)?;
+            while let Some(range) = iter.next()? {
+                ranges.push(range);
+            }
+            //declarations and abstract instances have no code, nothing below them does either
+            if ranges.is_empty() {
+                return Ok(());
+            }
+            if entry.tag() == constants::DW_TAG_subprogram {
+                frame_base = match entry.attr_value(constants::DW_AT_frame_base)? {
+                    Some(AttributeValue::Exprloc(expr)) => Some(expr),
+                    _ => None,
+                };
+            }
+            scope = Some(scopes.len());
+            scopes.push(Scope {
+                ranges,
+                frame_base,
+                variables: Vec::new(),
+            });
+        }
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let child_entry = child.entry();
+            let is_variable = matches!(
+                child_entry.tag(),
+                constants::DW_TAG_variable | constants::DW_TAG_formal_parameter
+            );
+            match scope {
+                Some(scope) if is_variable => {
+                    let Some(location) = child_entry.attr_value(constants::DW_AT_location)? else {
+                        continue;
+                    };
+                    let Some(name) = self.name(unit, child_entry) else {
+                        continue;
+                    };
+                    scopes[scope].variables.push(Variable { name, location });
+                }
+                _ => self.collect_node(unit, child, frame_base, scopes)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// DW_AT_name, through DW_AT_abstract_origin for inlined and out of line copies
+    fn name(
+        &self,
+        unit: &Unit<EStr<'a>>,
+        entry: &DebuggingInformationEntry<EStr<'a>>,
+    ) -> Option<String> {
+        if let Ok(Some(name)) = entry.attr_value(constants::DW_AT_name) {
+            let name = self.dwarf.attr_string(unit, name).ok()?;
+            return Some(name.to_string_lossy().into_owned());
+        }
+        match entry.attr_value(constants::DW_AT_abstract_origin).ok()?? {
+            AttributeValue::UnitRef(offset) => {
+                let origin = unit.entry(offset).ok()?;
+                let name = origin.attr_value(constants::DW_AT_name).ok()??;
+                let name = self.dwarf.attr_string(unit, name).ok()?;
+                Some(name.to_string_lossy().into_owned())
+            }
+            _ => None,
+        }
+    }
+
+    /// where the frame base is, as a register and offset
+    fn frame_base(
+        &self,
+        unit: &Unit<EStr<'a>>,
+        expr: Expression<EStr<'a>>,
+        cfa: Option<(Register, i64)>,
+    ) -> Option<(Register, i64)> {
+        match operations(unit, expr)?.as_slice() {
+            [Operation::Register { register }] => Some((*register, 0)),
+            [Operation::RegisterOffset {
+                register, offset, ..
+            }] => Some((*register, *offset)),
+            [Operation::CallFrameCFA] => cfa,
+            _ => None,
+        }
+    }
+
+    fn place(
+        &self,
+        unit: &Unit<EStr<'a>>,
+        location: AttributeValue<EStr<'a>>,
+        address: u64,
+        frame_base: &Option<(Register, i64)>,
+    ) -> Option<String> {
+        let expr = match location {
+            AttributeValue::Exprloc(expr) => expr,
+            AttributeValue::LocationListsRef(_) | AttributeValue::DebugLocListsIndex(_) => {
+                let mut iter = self.dwarf.attr_locations(unit, location).ok()??;
+                loop {
+                    let entry = iter.next().ok()??;
+                    if entry.range.begin <= address && address < entry.range.end {
+                        break entry.data;
+                    }
+                }
+            }
+            _ => return None,
+        };
+
+        //a variable split over several places is shown piece by piece
+        let ops = operations(unit, expr)?;
+        let pieces: Option<Vec<String>> = ops
+            .split(|op| matches!(op, Operation::Piece { .. }))
+            .filter(|piece| !piece.is_empty())
+            .map(|piece| self.describe(piece, frame_base))
+            .collect();
+        let pieces = pieces?;
+        (!pieces.is_empty()).then(|| pieces.join(":"))
+    }
+
+    /// a register or memory slot, None for values that are computed or optimized out
+    fn describe(
+        &self,
+        ops: &[Operation<EStr<'a>>],
+        frame_base: &Option<(Register, i64)>,
+    ) -> Option<String> {
+        match ops {
+            [Operation::Register { register }] => Some(register_name(self.arch, *register).into()),
+            [Operation::RegisterOffset {
+                register, offset, ..
+            }] => Some(self.slot(*register, *offset)),
+            [Operation::FrameOffset { offset }] => {
+                let (register, base) = (*frame_base)?;
+                Some(self.slot(register, base + offset))
+            }
+            [Operation::Address { address }] => Some(format!("[{:#x}]", address)),
+            _ => None,
+        }
+    }
+
+    fn slot(&self, register: Register, offset: i64) -> String {
+        let register = register_name(self.arch, register);
+        match offset {
+            0 => format!("[{}]", register),
+            o if o < 0 => format!("[{}-{:#x}]", register, o.unsigned_abs()),
+            o => format!("[{}+{:#x}]", register, o),
+        }
+    }
+}
+
+fn operations<'a>(
+    unit: &Unit<EStr<'a>>,
+    expr: Expression<EStr<'a>>,
+) -> Option<Vec<Operation<EStr<'a>>>> {
+    let mut ops = Vec::new();
+    let mut iter = expr.operations(unit.encoding());
+    while let Some(op) = iter.next().ok()? {
+        ops.push(op);
+    }
+    Some(ops)
+}
+
+fn contains(ranges: &[Range], address: u64) -> bool {
+    ranges.iter().any(|r| r.begin <= address && address < r.end)
+}
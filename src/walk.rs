@@ -1,7 +1,15 @@
+use crate::args::parse_address;
 use crate::config::WalkConfig;
 use crate::file_parser::InstructionDetail;
+use crate::file_parser::MachineFile;
+use crate::line_table::LineFlags;
+use crate::program_context::find_source_frames;
 use crate::program_context::CodeFile;
 use crate::program_context::CodeRegistry;
+use crate::program_context::MachineInstruction;
+use crate::symbols::SymbolIndex;
+use crate::types::find_type_layouts;
+use crate::variables::format_locations;
 use core::cmp::min;
 use crossterm::event::{
     self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, MouseEvent,
@@ -9,7 +17,7 @@ use crossterm::event::{
 };
 use crossterm::execute;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs;
 use std::io::{self};
@@ -46,10 +54,38 @@ impl Drop for TerminalCleanup {
     }
 }
 
+/// Where a selected instruction lives, the slices of a universal binary share addresses
+/// so the loaded file (fat slice or archive member) is part of the key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct AsmKey {
+    machine: Arc<Path>,
+    address: u64,
+}
+
+impl AsmKey {
+    fn new(machine: &Arc<Path>, address: u64) -> Self {
+        AsmKey {
+            machine: machine.clone(),
+            address,
+        }
+    }
+}
+
+impl Default for AsmKey {
+    fn default() -> Self {
+        AsmKey {
+            machine: Path::new("").into(),
+            address: 0,
+        }
+    }
+}
+
 struct SourceInfo {
     source_text: Rc<str>,
     file_name: Rc<str>,
     line_num: u32,
+    /// the sub-expression the instruction belongs to, in the line as the source pane shows it
+    span: Option<(usize, usize)>,
 }
 
 pub struct GlobalState<'arena> {
@@ -61,11 +97,29 @@ pub struct GlobalState<'arena> {
     show_lines: bool,
     pub config: WalkConfig,
 
-    selected_asm: BTreeMap<u64, (Cow<'arena, InstructionDetail>, Option<Rc<SourceInfo>>)>, //address -> (instructions,line text, file, line)
+    selected_asm: BTreeMap<AsmKey, (Cow<'arena, InstructionDetail>, Option<Rc<SourceInfo>>)>, //address -> (instructions,line text, file, line)
     // asm_cursor: usize,
-    cur_asm: u64,
+    cur_asm: AsmKey,
+    /// labels asm that has no line info
+    pub symbols: Arc<SymbolIndex>,
+    /// load bias added to shown addresses and taken off typed ones
+    pub bias: u64,
+    show_variables: bool,
+    /// variable locations of the selected instructions, filled while they are shown
+    variables: HashMap<AsmKey, Rc<str>>,
+    /// line table flags of the selected instructions, filled while they are shown
+    line_flags: HashMap<AsmKey, LineFlags>,
+    show_cfa: bool,
+    /// unwind rules of the selected instructions, filled while they are shown
+    cfa: HashMap<AsmKey, Rc<str>>,
+    /// address of the selected instructions as their file shows it (section offsets in an object)
+    /// and their operands disassembled there plus the bias
+    shown: HashMap<AsmKey, (u64, Box<str>)>,
 
     help_toggle: bool,
+    /// title and lines of the info popup (inline chain, type layout) while it is open
+    popup: Option<(String, Vec<String>)>,
+    popup_scroll: usize,
     auto_scroll_mode: AutoScrollMode,
     help_scroll: usize,
     show_file_locations: bool,
@@ -96,13 +150,23 @@ impl<'arena> GlobalState<'arena> {
             selected_asm: BTreeMap::new(),
 
             // asm_cursor:0,
-            cur_asm: 0,
+            cur_asm: AsmKey::default(),
+            symbols: Arc::default(),
+            bias: 0,
+            show_variables: false,
+            variables: HashMap::new(),
+            line_flags: HashMap::new(),
+            show_cfa: false,
+            cfa: HashMap::new(),
+            shown: HashMap::new(),
 
             help_toggle: false,
+            popup: None,
+            popup_scroll: 0,
             auto_scroll_mode: AutoScrollMode::Off,
             help_scroll: 0,
             show_file_locations: config.get_show_file_locations(),
-            config: config,
+            config,
             // asm_lines: BTreeMap::default()
         };
 
@@ -112,42 +176,119 @@ impl<'arena> GlobalState<'arena> {
 
     fn add_asm_line(
         &mut self,
-        debug: Option<&'arena [InstructionDetail]>,
+        debug: Option<&'arena [MachineInstruction]>,
         text: Rc<str>,
         file_path: &str,
         line_num: u32,
+        code_file: &CodeFile,
+        obj_path: &Path,
     ) {
         match debug {
             None => {}
             Some(data) => {
                 // If text is non-empty, we must provide file info
-                let file_info = if !text.is_empty() {
-                    Some(Rc::new(SourceInfo {
-                        file_name: file_path.into(),
-                        source_text: text.into(),
-                        line_num,
-                    }))
-                } else {
-                    None
-                };
+                if text.is_empty() {
+                    self.selected_asm.extend(data.iter().map(|(machine, x)| {
+                        (AsmKey::new(machine, x.address), (Cow::Borrowed(x), None))
+                    }));
+                    return;
+                }
 
-                self.selected_asm.extend(
-                    data.iter()
-                        .map(|x| (x.address, (Cow::Borrowed(x), file_info.clone()))),
-                );
+                //one info per column so instructions of the same expression share it
+                let mut infos: HashMap<Option<u32>, Rc<SourceInfo>> = HashMap::new();
+                for (machine, x) in data {
+                    let column = code_file.get_column(line_num, obj_path, machine, x.address);
+                    let info = infos.entry(column).or_insert_with(|| {
+                        Rc::new(SourceInfo {
+                            file_name: file_path.into(),
+                            source_text: text.clone(),
+                            line_num,
+                            span: column_span(code_file, line_num, column),
+                        })
+                    });
+                    self.selected_asm.insert(
+                        AsmKey::new(machine, x.address),
+                        (Cow::Borrowed(x), Some(info.clone())),
+                    );
+                }
+            }
+        }
+    }
+
+    /// finds the variable locations of selected instructions that have none yet
+    fn load_variables(&mut self, code_files: &CodeRegistry<'_, 'arena>) {
+        for key in self.selected_asm.keys() {
+            if self.variables.contains_key(key) {
+                continue;
+            }
+            let vars = code_files
+                .get_existing_machine(&key.machine)
+                .and_then(|machine| machine.get_variables().ok())
+                .map(|index| format_locations(&index.variables_at(key.address)))
+                .unwrap_or_default();
+            self.variables.insert(key.clone(), vars.into());
+        }
+    }
+
+    /// finds the line table flags of selected instructions that have none yet
+    fn load_line_flags(&mut self, code_files: &CodeRegistry<'_, 'arena>) {
+        for key in self.selected_asm.keys() {
+            if self.line_flags.contains_key(key) {
+                continue;
+            }
+            let flags = code_files
+                .get_existing_machine(&key.machine)
+                .and_then(|machine| machine.get_line_flags().ok())
+                .and_then(|index| index.flags_at(key.address))
+                .unwrap_or_default();
+            self.line_flags.insert(key.clone(), flags);
+        }
+    }
+
+    /// finds the unwind rules of selected instructions that have none yet
+    fn load_cfa(&mut self, code_files: &CodeRegistry<'_, 'arena>) {
+        for key in self.selected_asm.keys() {
+            if self.cfa.contains_key(key) {
+                continue;
+            }
+            let cfa = code_files
+                .get_existing_machine(&key.machine)
+                .and_then(|machine| machine.get_frames())
+                .and_then(|frames| frames.describe(key.address))
+                .unwrap_or_default();
+            self.cfa.insert(key.clone(), cfa.into());
+        }
+    }
+
+    /// finds the shown address and operands of selected instructions that have none yet
+    fn load_shown(&mut self, code_files: &CodeRegistry<'_, 'arena>) {
+        for (key, (ins, _)) in &self.selected_asm {
+            if self.shown.contains_key(key) {
+                continue;
             }
+            let shown = code_files
+                .get_existing_machine(&key.machine)
+                .and_then(|machine| {
+                    let address = machine.layout.shown_address(ins.address);
+                    let op_str = machine
+                        .shown_op_str(ins, address.wrapping_add(self.bias))
+                        .ok()?;
+                    Some((address, op_str))
+                })
+                .unwrap_or_else(|| (ins.address, ins.op_str.clone()));
+            self.shown.insert(key.clone(), shown);
         }
     }
 
-    fn remove_asm_line(&mut self, debug: Option<&'arena [InstructionDetail]>) {
-        for address in debug.unwrap_or_default().iter().map(|x| x.address) {
-            self.selected_asm.remove(&address);
+    fn remove_asm_line(&mut self, debug: Option<&'arena [MachineInstruction]>) {
+        for (machine, x) in debug.unwrap_or_default() {
+            self.selected_asm.remove(&AsmKey::new(machine, x.address));
         }
         self.cur_asm = min(
-            self.cur_asm,
+            self.cur_asm.clone(),
             self.selected_asm
                 .last_key_value()
-                .map(|(k, _)| *k)
+                .map(|(k, _)| k.clone())
                 .unwrap_or_default(),
         );
     }
@@ -181,11 +322,12 @@ impl<'arena> GlobalState<'arena> {
     #[inline]
     fn asm_up(&mut self) {
         // Move to the previous address if possible
-        if let Some((_, (prev_ins, _))) = self.selected_asm.range(..self.cur_asm).next_back() {
-            if prev_ins.get_end() == self.cur_asm {
-                self.cur_asm = prev_ins.address;
+        if let Some((prev, (prev_ins, _))) = self.selected_asm.range(..&self.cur_asm).next_back() {
+            let end = AsmKey::new(&prev.machine, prev_ins.get_end());
+            if end == self.cur_asm {
+                self.cur_asm = prev.clone();
             } else {
-                self.cur_asm = prev_ins.get_end();
+                self.cur_asm = end;
             }
         }
     }
@@ -205,15 +347,59 @@ impl<'arena> GlobalState<'arena> {
 
     #[inline]
     fn asm_down(&mut self) {
-        if let Some((addr, (ins, _))) = self.selected_asm.range((self.cur_asm)..).next() {
-            if *addr == self.cur_asm {
-                self.cur_asm = ins.get_end();
+        if let Some((key, (ins, _))) = self.selected_asm.range(&self.cur_asm..).next() {
+            if *key == self.cur_asm {
+                self.cur_asm = AsmKey::new(&key.machine, ins.get_end());
             } else {
-                self.cur_asm = *addr;
+                self.cur_asm = key.clone();
             }
         }
     }
 
+    /// The file of `obj_path` holding `address`, the one under the asm cursor when it does
+    fn machine_at(
+        &self,
+        obj_path: &Path,
+        address: u64,
+        code_files: &CodeRegistry<'_, 'arena>,
+    ) -> Option<Arc<Path>> {
+        let cur = &self.cur_asm.machine;
+        let holds = code_files.machine_paths(obj_path).contains(cur)
+            && code_files
+                .get_existing_machine(cur)
+                .is_some_and(|machine| machine.has_code_at(address));
+        match holds {
+            true => Some(cur.clone()),
+            false => code_files.machine_path_at(obj_path, address),
+        }
+    }
+
+    /// The placed address of a typed file address, in an object that is the offset in a section,
+    /// the first code section long enough (of the current machine first) is picked
+    fn placed_address(
+        &self,
+        obj_path: &Path,
+        shown: u64,
+        code_files: &CodeRegistry<'_, 'arena>,
+    ) -> u64 {
+        let mut machines = code_files.machine_paths(obj_path);
+        if let Some(cur) = machines.iter().position(|m| *m == self.cur_asm.machine) {
+            machines[..=cur].rotate_right(1);
+        }
+        machines
+            .iter()
+            .filter_map(|path| code_files.get_existing_machine(path))
+            .flat_map(|machine| {
+                machine
+                    .layout
+                    .placed_addresses(shown)
+                    .into_iter()
+                    .filter(|address| machine.has_code_at(*address))
+            })
+            .next()
+            .unwrap_or(shown)
+    }
+
     #[inline]
     fn asm_toggle(
         &mut self,
@@ -221,39 +407,55 @@ impl<'arena> GlobalState<'arena> {
         code_files: &mut CodeRegistry<'_, 'arena>,
     ) -> Result<(), Box<dyn Error>> {
         use std::collections::btree_map::Entry;
-        match self.selected_asm.entry(self.cur_asm) {
+        //before anything is selected the cursor has no file yet
+        if code_files
+            .get_existing_machine(&self.cur_asm.machine)
+            .is_none()
+        {
+            let address = self.cur_asm.address;
+            let machine = code_files
+                .machine_path_at(obj_path, address)
+                .ok_or("no loaded file holds this address")?;
+            self.cur_asm = AsmKey::new(&machine, address);
+        }
+
+        match self.selected_asm.entry(self.cur_asm.clone()) {
             Entry::Vacant(v) => {
-                let machine_file = code_files.get_existing_machine(obj_path).unwrap();
-                let ctx = machine_file.get_addr2line()?;
+                let machine_file = code_files
+                    .get_existing_machine(&v.key().machine)
+                    .ok_or("no loaded file holds this address")?;
+                let locator = machine_file.get_locator()?;
 
-                let Some(raw_asm) = machine_file.dissasm_address(self.cur_asm)? else {
+                let Some(raw_asm) = machine_file.dissasm_address(v.key().address)? else {
                     return Ok(());
                 };
 
                 if let Some(addr2line::Location {
                     file: Some(file),
                     line: Some(line),
-                    ..
-                }) = ctx.find_location(raw_asm.address)?
+                    column,
+                }) = locator.find_location(raw_asm.address)?
                 {
-                    let path = Path::new(file).into();
-                    let code_file = code_files.get_source_file(path, false)?;
+                    let path: Arc<Path> = machine_file.source_path(file).into();
+                    let code_file = code_files.get_source_file(path.clone(), false)?;
                     let text = code_file.get_line(line);
                     match text {
                         Some(t) => v.insert((
                             Cow::Owned(raw_asm),
                             Some(Rc::new(SourceInfo {
-                                file_name: file.into(),
+                                file_name: path.to_string_lossy().into(),
                                 source_text: sanitise(t.trim_start().to_string()).into(),
                                 line_num: line,
+                                span: column_span(code_file, line, column),
                             })),
                         )),
                         None => v.insert((
                             Cow::Owned(raw_asm),
                             Some(Rc::new(SourceInfo {
-                                file_name: file.into(),
+                                file_name: path.to_string_lossy().into(),
                                 source_text: "??".into(),
                                 line_num: line,
+                                span: None,
                             })),
                         )),
                     };
@@ -303,6 +505,10 @@ pub struct FileState<'me, 'arena> {
     pub file_scroll: usize,
     pub cursor: usize,
     pub file_path: String,
+    /// the file is missing locally and the text came embedded in the binary
+    from_binary: bool,
+    /// the file changed since the binary was built (DWARF 5 MD5 mismatch)
+    stale: bool,
 
     global: &'me mut GlobalState<'arena>,
     command_input: String,
@@ -335,6 +541,96 @@ impl<'me, 'arena> FileState<'me, 'arena> {
         self.file_scroll = target;
     }
 
+    /// Adds the asm of every frame of a backtrace, innermost first in `frames`.
+    ///
+    /// The first frame is the pc itself when `faulted` is set, any other frame is a return address
+    /// so the call right before it is shown. The innermost frame ends up selected.
+    pub fn select_frames(
+        &mut self,
+        frames: &[u64],
+        faulted: bool,
+        obj_path: &Path,
+        code_files: &mut CodeRegistry<'_, 'arena>,
+    ) -> Result<(), Box<dyn Error>> {
+        for (i, address) in frames.iter().enumerate().rev() {
+            let address = match i == 0 && faulted {
+                true => *address,
+                false => self
+                    .global
+                    .machine_at(obj_path, *address, code_files)
+                    .and_then(|machine| code_files.get_existing_machine(&machine))
+                    .and_then(|machine| call_before(machine, *address))
+                    .unwrap_or(address - 1),
+            };
+            self.jump_to_address(address, obj_path, code_files)?;
+        }
+        Ok(())
+    }
+
+    /// Opens the inline chain popup for the instruction under the asm cursor
+    fn show_inline_chain(
+        &mut self,
+        code_files: &mut CodeRegistry<'_, 'arena>,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some((ins, _)) = self.global.selected_asm.get(&self.global.cur_asm) else {
+            return Ok(());
+        };
+        let address = ins.address;
+        let machine_file = code_files
+            .get_existing_machine(&self.global.cur_asm.machine)
+            .ok_or("Failed to get machine file")?;
+        let locator = machine_file.get_locator()?;
+        let symbols = machine_file.get_symbols();
+        let remap = machine_file.source_remap.clone();
+        let shown = machine_file.layout.shown_address(address);
+
+        let frames = find_source_frames(&locator, code_files.asm, &symbols, address);
+        let mut lines = vec![
+            format!("{:#x}", shown.wrapping_add(self.global.bias)),
+            "innermost first, callers at their call sites".to_string(),
+            String::new(),
+        ];
+        for (i, frame) in frames.iter().enumerate() {
+            let file = frame.file.as_deref().map_or("<unknown>".into(), |f| {
+                remap.apply(Path::new(f)).display().to_string()
+            });
+            let line = frame.line.map_or("<unknown>".into(), |l| l.to_string());
+            let column = frame.column.map(|c| format!(":{}", c)).unwrap_or_default();
+            let function = frame.function.as_deref().unwrap_or("<unknown>");
+            let inlined = if i + 1 < frames.len() {
+                " (inlined)"
+            } else {
+                ""
+            };
+            lines.push(format!("#{:<2} {}{}", i, function, inlined));
+            lines.push(format!("    at {}:{}{}", file, line, column));
+        }
+        self.global.popup = Some(("Inline Chain".to_string(), lines));
+        self.global.popup_scroll = 0;
+        Ok(())
+    }
+
+    /// Opens a popup with the layout of the type called `name`
+    fn show_type(&mut self, name: &str, obj_path: &Path, code_files: &CodeRegistry<'_, 'arena>) {
+        let mut lines = Vec::new();
+        for machine in code_files.get_machines(obj_path) {
+            let Ok(dwarf) = machine.load_dwarf() else {
+                continue;
+            };
+            for layout in find_type_layouts(&dwarf, name).unwrap_or_default() {
+                if !lines.is_empty() {
+                    lines.push(String::new());
+                }
+                lines.extend(layout);
+            }
+        }
+        if lines.is_empty() {
+            lines.push(format!("no struct, class, union or enum named {:?}", name));
+        }
+        self.global.popup = Some((format!("Type {}", name), lines));
+        self.global.popup_scroll = 0;
+    }
+
     #[inline]
     fn jump_to_address(
         &mut self,
@@ -342,48 +638,58 @@ impl<'me, 'arena> FileState<'me, 'arena> {
         obj_path: &Path,
         code_files: &mut CodeRegistry<'_, 'arena>,
     ) -> Result<(), Box<dyn Error>> {
+        let machine = self
+            .global
+            .machine_at(obj_path, target_addr, code_files)
+            .ok_or("Failed to get machine file")?;
+        let target = AsmKey::new(&machine, target_addr);
+
         // First check if address is already in selected_asm (including if it's in the middle of an instruction)
-        for (addr, (ins, _)) in self.global.selected_asm.range(..=target_addr).rev() {
+        for (key, (ins, _)) in self.global.selected_asm.range(..=&target).rev() {
+            if key.machine != machine {
+                break;
+            }
             if ins.address <= target_addr && target_addr < ins.get_end() {
                 // Address is within this instruction
-                self.global.cur_asm = *addr;
+                self.global.cur_asm = key.clone();
                 return Ok(());
             }
         }
 
         // Try to find debug info for the target address
         let machine_file = code_files
-            .get_existing_machine(obj_path)
+            .get_existing_machine(&machine)
             .ok_or("Failed to get machine file")?;
-        let ctx = machine_file.get_addr2line()?;
+        let locator = machine_file.get_locator()?;
 
         // Try direct lookup first
         if let Some(raw_asm) = machine_file.dissasm_address(target_addr)? {
             if let Some(addr2line::Location {
                 file: Some(file),
                 line: Some(line),
-                ..
-            }) = ctx.find_location(raw_asm.address)?
+                column,
+            }) = locator.find_location(raw_asm.address)?
             {
-                let path = Path::new(file).into();
-                let code_file = code_files.get_source_file(path, false)?;
+                let path: Arc<Path> = machine_file.source_path(file).into();
+                let code_file = code_files.get_source_file(path.clone(), false)?;
                 let text = code_file.get_line(line);
                 let source_text = match text {
                     Some(t) => sanitise(t.trim_start().to_string()).into(),
                     None => "<??>".into(),
                 };
                 self.global.selected_asm.insert(
-                    target_addr,
+                    target.clone(),
                     (
                         Cow::Owned(raw_asm),
                         Some(Rc::new(SourceInfo {
-                                file_name: file.into(),
-                                source_text,
-                                line_num: line,
-                            }))
+                            file_name: path.to_string_lossy().into(),
+                            source_text,
+                            line_num: line,
+                            span: column_span(code_file, line, column),
+                        })),
                     ),
                 );
-                self.global.cur_asm = target_addr;
+                self.global.cur_asm = target;
                 return Ok(());
             }
         }
@@ -397,39 +703,48 @@ impl<'me, 'arena> FileState<'me, 'arena> {
                 if let Some(addr2line::Location {
                     file: Some(file),
                     line: Some(line),
-                    ..
-                }) = ctx.find_location(raw_asm.address)?
+                    column,
+                }) = locator.find_location(raw_asm.address)?
                 {
-                    let path = Path::new(file).into();
-                    let code_file = code_files.get_source_file(path, false)?;
+                    let path: Arc<Path> = machine_file.source_path(file).into();
+                    let code_file = code_files.get_source_file(path.clone(), false)?;
                     let text = code_file.get_line(line);
                     let source_text = match text {
                         Some(t) => sanitise(t.trim_start().to_string()).into(),
                         None => "<??>".into(),
                     };
                     self.global.selected_asm.insert(
-                        check_addr,
+                        AsmKey::new(&machine, check_addr),
                         (
                             Cow::Owned(raw_asm),
                             Some(Rc::new(SourceInfo {
-                                file_name: file.into(),
+                                file_name: path.to_string_lossy().into(),
                                 source_text,
                                 line_num: line,
-                            }))
+                                span: column_span(code_file, line, column),
+                            })),
                         ),
                     );
-                    self.global.cur_asm = check_addr;
+                    self.global.cur_asm = AsmKey::new(&machine, check_addr);
                     return Ok(());
                 }
             }
         }
 
+        // No line info anywhere near it, show the bare instruction (labeled by its symbol)
+        if let Some(raw_asm) = machine_file.dissasm_address(target_addr)? {
+            self.global
+                .selected_asm
+                .insert(target.clone(), (Cow::Owned(raw_asm), None));
+            self.global.cur_asm = target;
+            return Ok(());
+        }
+
         // If we couldn't find anything, fall back to the closest existing address
-        if let Some((closest_addr, _)) = self.global.selected_asm.range(..=target_addr).next_back()
-        {
-            self.global.cur_asm = *closest_addr;
-        } else if let Some((first_addr, _)) = self.global.selected_asm.iter().next() {
-            self.global.cur_asm = *first_addr;
+        if let Some((closest, _)) = self.global.selected_asm.range(..=&target).next_back() {
+            self.global.cur_asm = closest.clone();
+        } else if let Some((first, _)) = self.global.selected_asm.iter().next() {
+            self.global.cur_asm = first.clone();
         }
 
         Ok(())
@@ -440,7 +755,7 @@ struct Line<'data> {
     content: Rc<str>,
     is_selected: bool,
     line_number: usize, // Optionally store the line number
-    debug_info: Option<Option<&'data [InstructionDetail]>>, // debug_info: Option<String>,  // Placeholder for future debug information
+    debug_info: Option<Option<&'data [MachineInstruction]>>, // debug_info: Option<String>,  // Placeholder for future debug information
 }
 
 impl<'data> Line<'data> {
@@ -458,7 +773,7 @@ impl<'data> Line<'data> {
         &mut self,
         code_file: &'data CodeFile,
         obj_path: Arc<Path>,
-    ) -> Result<Option<&'data [InstructionDetail]>, Box<dyn Error>> {
+    ) -> Result<Option<&'data [MachineInstruction]>, Box<dyn Error>> {
         // eprintln!("LOAD_DEBUG for line {} file {}", self.line_number, obj_path.display());
 
         match self.debug_info {
@@ -696,6 +1011,8 @@ pub fn load_file<'b, 'arena>(
     Ok(FileState {
         file_content: read_file_lines(code_file),
         file_path: path.display().to_string(),
+        from_binary: code_file.from_binary,
+        stale: code_file.stale,
         file_scroll: 0,
         cursor: 0,
         // asm_cursor :0,
@@ -746,10 +1063,31 @@ pub fn handle_file_input<'arena>(
                             }
                             // Try parsing as hex address first
                             _ => {
-                                if let Some(hex_str) = command.strip_prefix("0x") {
+                                //type names are case sensitive so they come from the raw input
+                                let raw = state.command_input.trim().to_string();
+                                if let Some(name) = raw.strip_prefix("type ") {
+                                    state.show_type(name.trim(), &obj_path, code_files);
+                                } else if let Some(base) = command
+                                    .strip_prefix("base")
+                                    .or_else(|| command.strip_prefix("bias"))
+                                {
+                                    let base = base.trim();
+                                    if base.is_empty() {
+                                        state.global.bias = 0;
+                                    } else if let Ok(base) = parse_address(base) {
+                                        state.global.bias = base;
+                                    }
+                                    state.global.shown.clear();
+                                } else if let Some(hex_str) = command.strip_prefix("0x") {
                                     if let Ok(addr) =
                                         u64::from_str_radix(&hex_str.to_lowercase(), 16)
                                     {
+                                        //typed addresses are runtime ones
+                                        let addr = state.global.placed_address(
+                                            &obj_path,
+                                            addr.wrapping_sub(state.global.bias),
+                                            code_files,
+                                        );
                                         if let Err(_e) =
                                             state.jump_to_address(addr, &obj_path, code_files)
                                         {
@@ -769,10 +1107,8 @@ pub fn handle_file_input<'arena>(
                             state.command_mode = false;
                         }
                     }
-                    KeyCode::Char(c) => {
-                        if !c.is_control() {
-                            state.command_input.push(c);
-                        }
+                    KeyCode::Char(c) if !c.is_control() => {
+                        state.command_input.push(c);
                     }
                     _ => {}
                 }
@@ -780,6 +1116,21 @@ pub fn handle_file_input<'arena>(
                 return Ok(FileResult::KeepGoing);
             }
 
+            if state.global.popup.is_some() {
+                match code {
+                    KeyCode::Up | KeyCode::Char('w') => {
+                        state.global.popup_scroll = state.global.popup_scroll.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('s') => {
+                        state.global.popup_scroll = state.global.popup_scroll.saturating_add(1);
+                    }
+                    KeyCode::Char('i') | KeyCode::Esc | KeyCode::Enter => state.global.popup = None,
+                    KeyCode::Char('q') => return Ok(FileResult::Exit),
+                    _ => {}
+                }
+                return Ok(FileResult::KeepGoing);
+            }
+
             if state.global.help_toggle {
                 match code {
                     KeyCode::Up | KeyCode::Char('w') => {
@@ -830,33 +1181,32 @@ pub fn handle_file_input<'arena>(
                 KeyCode::Char('w') => state.global.asm_up(),
                 KeyCode::Char('s') => state.global.asm_down(),
                 KeyCode::Char(' ') => state.global.asm_toggle(&obj_path, code_files)?,
+                KeyCode::Char('i') => state.show_inline_chain(code_files)?,
+                KeyCode::Char('v') => state.global.show_variables = !state.global.show_variables,
+                KeyCode::Char('c') => state.global.show_cfa = !state.global.show_cfa,
 
-                KeyCode::Up => {
-                    if state.cursor > 0 {
-                        state.cursor -= 1;
+                KeyCode::Up if state.cursor > 0 => {
+                    state.cursor -= 1;
 
-                        // Scroll up if cursor is above the visible range
-                        if state.cursor < state.file_scroll {
-                            state.file_scroll = state.cursor;
-                        }
+                    // Scroll up if cursor is above the visible range
+                    if state.cursor < state.file_scroll {
+                        state.file_scroll = state.cursor;
                     }
                 }
-                KeyCode::Down => {
-                    if state.cursor < state.file_content.len().saturating_sub(1) {
-                        state.cursor += 1;
+                KeyCode::Down if state.cursor < state.file_content.len().saturating_sub(1) => {
+                    state.cursor += 1;
 
-                        // Scroll down if cursor goes below the visible range
-                        let max_visible_lines = state.file_content.len().saturating_sub(1);
-                        if state.cursor >= state.file_scroll + max_visible_lines {
-                            state.file_scroll = state.cursor - max_visible_lines + 1;
-                        }
+                    // Scroll down if cursor goes below the visible range
+                    let max_visible_lines = state.file_content.len().saturating_sub(1);
+                    if state.cursor >= state.file_scroll + max_visible_lines {
+                        state.file_scroll = state.cursor - max_visible_lines + 1;
                     }
                 }
                 KeyCode::Enter => {
                     // Toggle selection of the current line under the cursor
                     if let Some(line) = state.file_content.get_mut(state.cursor) {
                         line.is_selected = !line.is_selected;
-                        let info = line.load_debug(code_file, obj_path);
+                        let info = line.load_debug(code_file, obj_path.clone());
 
                         if line.is_selected {
                             state.global.add_asm_line(
@@ -864,6 +1214,8 @@ pub fn handle_file_input<'arena>(
                                 line.content.clone(),
                                 state.file_path.as_str(),
                                 line.line_number as u32,
+                                code_file,
+                                &obj_path,
                             )
                         } else {
                             state.global.remove_asm_line(info?)
@@ -912,25 +1264,23 @@ pub fn handle_file_input<'arena>(
         }
         Event::Mouse(MouseEvent { kind, .. }) => {
             match kind {
-                MouseEventKind::ScrollDown => {
-                    if state.cursor < state.file_content.len().saturating_sub(1) {
-                        state.cursor += 1;
+                MouseEventKind::ScrollDown
+                    if state.cursor < state.file_content.len().saturating_sub(1) =>
+                {
+                    state.cursor += 1;
 
-                        // Scroll down if cursor goes below the visible range
-                        let max_visible_lines = state.file_content.len().saturating_sub(1);
-                        if state.cursor >= state.file_scroll + max_visible_lines {
-                            state.file_scroll = state.cursor - max_visible_lines + 1;
-                        }
+                    // Scroll down if cursor goes below the visible range
+                    let max_visible_lines = state.file_content.len().saturating_sub(1);
+                    if state.cursor >= state.file_scroll + max_visible_lines {
+                        state.file_scroll = state.cursor - max_visible_lines + 1;
                     }
                 }
-                MouseEventKind::ScrollUp => {
-                    if state.cursor > 0 {
-                        state.cursor -= 1;
+                MouseEventKind::ScrollUp if state.cursor > 0 => {
+                    state.cursor -= 1;
 
-                        // Scroll up if cursor is above the visible range
-                        if state.cursor < state.file_scroll {
-                            state.file_scroll = state.cursor;
-                        }
+                    // Scroll up if cursor is above the visible range
+                    if state.cursor < state.file_scroll {
+                        state.file_scroll = state.cursor;
                     }
                 }
                 _ => {}
@@ -941,6 +1291,21 @@ pub fn handle_file_input<'arena>(
     Ok(FileResult::KeepGoing)
 }
 
+/// The call instruction that returns to `return_address`
+fn call_before(machine: &MachineFile, return_address: u64) -> Option<u64> {
+    //x86 instructions are at most 15 bytes, so one of these decodes to the call ending there
+    (1..=15)
+        .map(|len| return_address.checked_sub(len))
+        .find_map(|start| {
+            let start = start?;
+            let ins = machine.dissasm_address(start).ok()??;
+            //exact names, arm's `ble`/`bls`/`blt` are conditional branches
+            let is_call = ins.mnemonic.starts_with("call")
+                || matches!(&*ins.mnemonic, "bl" | "blr" | "blx" | "jal" | "jalr");
+            (is_call && ins.get_end() == return_address).then_some(start)
+        })
+}
+
 /// make text consistently renderble
 fn sanitise(mut s: String) -> String {
     s = s.replace('\t', "  ");
@@ -949,6 +1314,14 @@ fn sanitise(mut s: String) -> String {
     s
 }
 
+/// [`CodeFile::column_span`] moved to the sanitised text the walk shows
+fn column_span(code_file: &CodeFile, line: u32, column: Option<u32>) -> Option<(usize, usize)> {
+    let (start, end) = code_file.column_span(line, column?)?;
+    let raw = code_file.get_line(line)?;
+    let shown = |end: usize| sanitise(raw[..end].to_string()).len();
+    Some((shown(start), shown(end)))
+}
+
 fn read_file_lines(code_file: &CodeFile) -> Vec<Line<'static>> {
     // let file = File::open(path)?;
     // let reader = io::BufReader::new(file);
@@ -968,7 +1341,7 @@ fn read_file_lines(code_file: &CodeFile) -> Vec<Line<'static>> {
 }
 
 // Helper function to create a line without a line number and styling
-fn create_line<'a>(line: &Line, show_lines: bool) -> ListItem<'a> {
+fn create_line<'a>(line: &Line, show_lines: bool, span: Option<(usize, usize)>) -> ListItem<'a> {
     // eprintln!("displaying {:?}",line.content);
 
     let line_style = if line.is_selected {
@@ -995,8 +1368,29 @@ fn create_line<'a>(line: &Line, show_lines: bool) -> ListItem<'a> {
         (a, _) => a.to_string(),
     };
 
-    let line_content_span = Span::styled(line_text, line_style);
-    ListItem::new(Spans::from(vec![line_number_span, line_content_span]))
+    //underline the expression the selected instruction came from
+    let parts = span.and_then(|(start, end)| {
+        Some((
+            line_text.get(..start)?,
+            line_text.get(start..end)?,
+            line_text.get(end..)?,
+        ))
+    });
+    let mut spans = vec![line_number_span];
+    match parts {
+        Some((before, expr, after)) => {
+            spans.push(Span::styled(before.to_string(), line_style));
+            spans.push(Span::styled(
+                expr.to_string(),
+                line_style
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::UNDERLINED),
+            ));
+            spans.push(Span::styled(after.to_string(), line_style));
+        }
+        None => spans.push(Span::styled(line_text, line_style)),
+    }
+    ListItem::new(Spans::from(spans))
 }
 
 pub fn render_file_asm_viewer(
@@ -1037,12 +1431,40 @@ pub fn render_file_asm_viewer(
         }
 
         // Source file block
-        let file_block = Block::default().borders(Borders::ALL).title(Span::styled(
-            format!("File Viewer - {}", state.file_path),
+        let title = match state.from_binary {
+            true => format!(
+                "File Viewer - {} (source embedded in the binary)",
+                state.file_path
+            ),
+            false => format!("File Viewer - {}", state.file_path),
+        };
+        let mut title = vec![Span::styled(
+            title,
             Style::default()
                 .fg(Color::Blue)
                 .add_modifier(Modifier::BOLD),
-        ));
+        )];
+        if state.stale {
+            title.push(Span::styled(
+                " STALE: edited since the binary was built, lines may not match ",
+                Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Red)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        }
+        let file_block = Block::default()
+            .borders(Borders::ALL)
+            .title(Spans::from(title));
+
+        //where in this file the instruction under the asm cursor came from
+        let highlight = state
+            .global
+            .selected_asm
+            .get(&state.global.cur_asm)
+            .and_then(|(_, info)| info.as_ref())
+            .filter(|info| *info.file_name == *state.file_path)
+            .and_then(|info| Some((info.line_num as usize, info.span?)));
 
         let source_items: Vec<ListItem> = state
             .file_content
@@ -1052,7 +1474,10 @@ pub fn render_file_asm_viewer(
             // .enumerate()
             .map(|line| {
                 // let asm_list = make_assembly_inner(code_file.get_asm(&(line.line_number as u32),obj_path.clone()));
-                create_line(line, state.global.show_lines) //,asm_list)
+                let span = highlight
+                    .filter(|(line_num, _)| *line_num == line.line_number)
+                    .map(|(_, span)| span);
+                create_line(line, state.global.show_lines, span) //,asm_list)
             })
             .collect();
 
@@ -1083,6 +1508,12 @@ pub fn render_file_asm_viewer(
             f.render_widget(command, command_area);
         }
 
+        if let Some((title, content)) = &state.global.popup {
+            let lines: Vec<&str> = content.iter().map(String::as_str).collect();
+            let scroll = state.global.popup_scroll.min(lines.len().saturating_sub(1));
+            render_scrollable_popup(f, title, &lines, 80, 80, scroll);
+        }
+
         if state.global.help_toggle {
             render_help_popup(f, state.global.help_scroll);
         }
@@ -1114,10 +1545,10 @@ fn make_assembly_inner<'a>(state: &GlobalState, max_visible_lines: usize) -> Lis
 
     let start_key = state
         .selected_asm
-        .range(..state.cur_asm)
+        .range(..&state.cur_asm)
         .rev()
         .take((1 + max_visible_lines * 2) / 3)
-        .fold(state.cur_asm, |_, (a, _)| *a);
+        .fold(&state.cur_asm, |_, (a, _)| a);
 
     let mut iter = state.selected_asm.range(start_key..).peekable();
 
@@ -1127,7 +1558,7 @@ fn make_assembly_inner<'a>(state: &GlobalState, max_visible_lines: usize) -> Lis
     };
 
     match iter.peek() {
-        Some((0, _)) => {}
+        Some((key, _)) if key.address == 0 => {}
         Some(_) => {
             asm_items.push(make_dots(false));
         }
@@ -1136,46 +1567,79 @@ fn make_assembly_inner<'a>(state: &GlobalState, max_visible_lines: usize) -> Lis
         }
     }
 
-    while let Some((_, (ins, file_info))) = iter.next() {
+    while let Some((key, (ins, file_info))) = iter.next() {
         if asm_items.len() >= max_visible_lines {
             break;
         }
 
-        let display_text = if state.show_file_locations {
-            match file_info {
-                Some(info) => format!("{}:{}", info.file_name, info.line_num),
-                None => "<??>".to_string(),
-            }
-        } else {
-            match file_info {
-                Some(info) => info.source_text.to_string(),
-                None => "<??>".to_string(),
+        let display_text = match file_info {
+            Some(info) if state.show_file_locations => {
+                format!("{}:{}", info.file_name, info.line_num)
             }
+            Some(info) => info.source_text.to_string(),
+            None => state
+                .symbols
+                .label(ins.address)
+                .unwrap_or_else(|| "<??>".to_string()),
         };
 
+        let (file_address, op_str) = state
+            .shown
+            .get(key)
+            .map_or((ins.address, &ins.op_str), |(address, op_str)| {
+                (*address, op_str)
+            });
+        //with a bias the file address is kept next to the runtime one
+        let address = match state.bias {
+            0 => format!("{:#010x}", file_address),
+            bias => format!(
+                "{:#014x} ({:#x})",
+                file_address.wrapping_add(bias),
+                file_address
+            ),
+        };
+        //flags, unwind rules and variables go before the source text, which is the part that can be cut off
+        let vars = match state.variables.get(key) {
+            Some(vars) if state.show_variables && !vars.is_empty() => format!("{} ", vars),
+            _ => String::new(),
+        };
+        let cfa = match state.cfa.get(key) {
+            Some(cfa) if state.show_cfa && !cfa.is_empty() => format!("{} ", cfa),
+            _ => String::new(),
+        };
+        let flags = state.line_flags.get(key).copied().unwrap_or_default();
+        let markers = match flags.to_string() {
+            m if m.is_empty() => m,
+            m => format!("{} ", m),
+        };
         let formatted_instruction = format!(
-            "{:#010x}: {:<6} {:<30} {:<30}",
-            ins.address, ins.mnemonic, ins.op_str, display_text,
+            "{}: {:<6} {:<30} {}{}{}{:<30}",
+            address, ins.mnemonic, op_str, markers, cfa, vars, display_text,
         );
 
+        //instructions in the middle of a statement are dimmed, breakpoints would not land there
+        let mut style = Style::default().fg(Color::Cyan);
+        if !flags.is_stmt {
+            style = style.add_modifier(Modifier::DIM);
+        }
         asm_items.push(
-            ListItem::new(vec![Spans::from(formatted_instruction)]).style(maybe_highlight(
-                ins.address == state.cur_asm,
-                Style::default().fg(Color::Cyan),
-            )),
+            ListItem::new(vec![Spans::from(formatted_instruction)])
+                .style(maybe_highlight(*key == state.cur_asm, style)),
         );
 
         if asm_items.len() >= max_visible_lines {
             break;
         }
 
-        let Some((next_address, _)) = iter.peek() else {
-            let selected = ins.get_end() <= state.cur_asm;
+        let end = AsmKey::new(&key.machine, ins.get_end());
+        let Some((next, _)) = iter.peek() else {
+            let selected = end <= state.cur_asm;
             asm_items.push(make_dots(selected));
             break;
         };
 
-        let missing_range = ins.get_end()..**next_address;
+        //the gap up to the next instruction, or to the next file's
+        let missing_range = end..(*next).clone();
         if !missing_range.is_empty() {
             let selected = missing_range.contains(&state.cur_asm);
             asm_items.push(make_dots(selected));
@@ -1286,6 +1750,14 @@ impl<'me, 'arena> TerminalSession<'me, 'arena> {
         loop {
             wait_frame_start(last_frame, file_state.global.config.get_frame_min_time())?;
 
+            if file_state.global.show_variables {
+                file_state.global.load_variables(code_files);
+            }
+            file_state.global.load_line_flags(code_files);
+            if file_state.global.show_cfa {
+                file_state.global.load_cfa(code_files);
+            }
+            file_state.global.load_shown(code_files);
             render_file_asm_viewer(terminal, file_state)?;
             let res = handle_file_input(file_state, code_files, code_file, obj_file.clone())?;
             match res {
@@ -1373,17 +1845,29 @@ pub fn render_help_popup(frame: &mut Frame<CrosstermBackend<io::Stdout>>, help_s
         "  Enter      - Toggle selection of the current line",
         "              and load/unload associated assembly",
         "              (auto-scrolls when enabled)",
+        "  i          - Show the inline chain of the current address",
+        "              (every caller with its call site)",
+        "  v          - Toggle where local variables are (register/stack slot)",
+        "  c          - Toggle the CFA rule and saved registers of each instruction",
         "",
         "Assembly View:",
         "  w          - Scroll assembly view up",
         "  s          - Scroll assembly view down",
         "  Space      - Toggle selection of the current address",
         "              and load/unload associated assembly",
+        "  dimmed     - Not a statement boundary (is_stmt off)",
+        "              prologue_end, epilogue_begin and discriminators",
+        "              are shown from the line table",
         "",
         "Command Bar:",
         "  0-9        - Start a command (numbers jump to a line)",
         "  :          - Also opens the command bar",
         "  0xADDR     - Jump to hexadecimal address (e.g., 0x401000)",
+        "              (in an object file the offset in a section)",
+        "  :type NAME - Show the layout of a struct/class/union/enum",
+        "              (w/s scroll it, Esc closes)",
+        "  :base 0xADDR - Set the load bias, addresses are shown and",
+        "              typed as runtime ones (:base alone resets it)",
         "  Enter      - Run command and close",
         "  Backspace  - Delete, closing when empty",
         "  Esc        - Close the command bar without running",